@@ -0,0 +1,18 @@
+pub mod intcode;
+
+use std::env;
+use std::fs;
+use std::process;
+use intcode::asm;
+
+fn main() {
+  let path = env::args().nth(1).expect("Usage: asm <source.s>");
+  let src = fs::read_to_string(&path).expect("Cannot read source!");
+  match asm::assemble(&src) {
+    Ok(program) => print!("{}", asm::to_text(&program)),
+    Err(e) => {
+      eprintln!("{}: {}", path, e);
+      process::exit(1);
+    },
+  }
+}
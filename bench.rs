@@ -0,0 +1,108 @@
+pub mod intcode;
+
+use std::env;
+use std::time::{Duration, Instant};
+use intcode::{load_program, Interpreter, Network};
+
+fn read_program(path: &str) -> Vec<i64> {
+  load_program(path).unwrap_or_else(|e| panic!("{}", e))
+}
+
+fn interpreter(program: &[i64], cached: bool) -> Interpreter {
+  let mut interpreter = Interpreter::new(program.to_vec());
+  interpreter.mem.set_decode_cache(cached);
+  interpreter
+}
+
+// day09 part2, the BOOST program in sensor boost mode
+fn boost(program: &[i64], cached: bool) -> i64 {
+  let mut interpreter = interpreter(program, cached);
+  interpreter.stdin.push_back(2);
+  interpreter.run_to_halt().expect("BOOST program failed!");
+  *interpreter.stdout.back().expect("No output from BOOST program!")
+}
+
+// day07 part2, all 120 phase permutations of the amplifier feedback loop
+fn amplifiers(program: &[i64], cached: bool) -> i64 {
+  let names = ["A", "B", "C", "D", "E"];
+  let mut max = 0;
+  for phases in permutations(vec![5, 6, 7, 8, 9]) {
+    let mut network = Network::new();
+    for (name, phase) in names.iter().zip(phases) {
+      network.node(name, interpreter(program, cached)).input(name, phase);
+    }
+    network.input("A", 0)
+      .edge("A", "B")
+      .edge("B", "C")
+      .edge("C", "D")
+      .edge("D", "E")
+      .edge("E", "A");
+    network.run().expect("Amplifier program failed!");
+    max = max.max(network.last_output("E").expect("No output from amplifier program!"));
+  }
+  max
+}
+
+fn permutations(vals: Vec<i64>) -> Vec<Vec<i64>> {
+  if vals.len() <= 1 {
+    return vec![vals];
+  }
+  let mut perms = Vec::new();
+  for i in 0..vals.len() {
+    let mut rest = vals.clone();
+    let first = rest.remove(i);
+    for mut perm in permutations(rest) {
+      perm.insert(0, first);
+      perms.push(perm);
+    }
+  }
+  perms
+}
+
+// Best of `rounds` runs, the result has to be the same every time
+fn time<F: Fn() -> i64>(rounds: usize, run: F) -> (Duration, i64) {
+  let mut best = None;
+  let mut result = None;
+  for _ in 0..rounds {
+    let start = Instant::now();
+    let res = run();
+    let elapsed = start.elapsed();
+    if result.is_some() && result != Some(res) {
+      panic!("Result changed between runs!");
+    }
+    result = Some(res);
+    best = Some(match best {
+      Some(best) if best < elapsed => best,
+      _ => elapsed,
+    });
+  }
+  (best.unwrap(), result.unwrap())
+}
+
+fn compare<F: Fn(bool) -> i64>(name: &str, rounds: usize, run: F) {
+  let (cached, res_cached) = time(rounds, || run(true));
+  let (uncached, res_uncached) = time(rounds, || run(false));
+  if res_cached != res_uncached {
+    panic!("{}: cached run gave {}, uncached {}!", name, res_cached, res_uncached);
+  }
+  println!("{:<11} cached {:>10.3?}  uncached {:>10.3?}  speedup {:.2}x",
+    name, cached, uncached, uncached.as_secs_f64() / cached.as_secs_f64());
+}
+
+// Compares the decode cache against decoding every step:
+// bench <day09 program> <day07 program> [rounds]
+fn main() {
+  let args: Vec<String> = env::args().collect();
+  if args.len() < 3 {
+    panic!("Usage: bench <inputs/day09.txt> <inputs/day07.txt> [rounds]");
+  }
+  let rounds = match args.get(3) {
+    Some(rounds) => rounds.parse().expect("Invalid number of rounds!"),
+    None => 5,
+  };
+
+  let boost_program = read_program(&args[1]);
+  compare("day09 BOOST", rounds, |cached| boost(&boost_program, cached));
+  let amp_program = read_program(&args[2]);
+  compare("day07 loop", rounds, |cached| amplifiers(&amp_program, cached));
+}
@@ -0,0 +1,31 @@
+pub mod intcode;
+
+use std::env;
+use std::fs;
+use intcode::load_program;
+use intcode::cfg::Cfg;
+
+fn read_program(path: &str) -> Vec<i64> {
+  load_program(path).unwrap_or_else(|e| panic!("{}", e))
+}
+
+// Control-flow graph of a program as Graphviz source: cfg <program> [out.dot]
+// Without an output file the graph goes to stdout.
+fn main() {
+  let args: Vec<String> = env::args().collect();
+  if args.len() < 2 {
+    panic!("Usage: cfg <inputs/dayNN.txt> [out.dot]");
+  }
+
+  let cfg = Cfg::recover(&read_program(&args[1]));
+  match args.get(2) {
+    Some(path) => {
+      fs::write(path, cfg.to_dot()).expect("Cannot write output file!");
+      println!("{} blocks", cfg.blocks.len());
+      for addr in cfg.dynamic_jumps() {
+        println!("dynamic jump at {}", addr);
+      }
+    },
+    None => print!("{}", cfg.to_dot()),
+  }
+}
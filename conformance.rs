@@ -0,0 +1,60 @@
+pub mod intcode;
+
+use std::env;
+use std::fs;
+use std::process;
+use intcode::conformance::{load_cases, run_transpiled};
+
+// Runs conformance cases against the interpreter: conformance [--transpiled]
+// [cases...], every file in conformance/ by default. With `--transpiled` they
+// run through `transpile` output instead, built with rustc. The tests in
+// intcode/conformance.rs run the same files both ways.
+fn main() {
+  let mut paths: Vec<String> = env::args().skip(1).collect();
+  let transpiled = match paths.iter().position(|arg| arg == "--transpiled") {
+    Some(idx) => {
+      paths.remove(idx);
+      true
+    },
+    None => false,
+  };
+  if paths.is_empty() {
+    paths = fs::read_dir("conformance").expect("No conformance/ directory found!")
+      .map(|entry| entry.expect("Cannot list conformance/!").path().to_string_lossy().into_owned())
+      .filter(|path| path.ends_with(".cases"))
+      .collect();
+    paths.sort();
+  }
+
+  let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+  for path in &paths {
+    let cases = match load_cases(path) {
+      Ok(cases) => cases,
+      Err(e) => panic!("Invalid cases in {}: {}", path, e),
+    };
+    let results = if transpiled {
+      let dir = env::temp_dir().join("intcode-conformance");
+      run_transpiled(&cases, &dir).unwrap_or_else(|e| panic!("Cannot run {} transpiled: {}", path, e))
+    } else {
+      cases.iter().map(|case| Some(case.run())).collect()
+    };
+    for (case, result) in cases.iter().zip(results) {
+      match result {
+        None => skipped += 1,
+        Some(Ok(())) => passed += 1,
+        Some(Err(msg)) => {
+          println!("FAIL {} ({}:{}): {}", case.name, path, case.line, msg);
+          failed += 1;
+        },
+      }
+    }
+  }
+  if skipped > 0 {
+    println!("{} passed, {} failed, {} skipped", passed, failed, skipped);
+  } else {
+    println!("{} passed, {} failed", passed, failed);
+  }
+  if failed > 0 {
+    process::exit(1);
+  }
+}
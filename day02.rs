@@ -1,43 +1,17 @@
-use std::fs;
+pub mod intcode;
 
-const ADD: usize = 1;
-const MULT: usize = 2;
-const HALT: usize = 99;
+use intcode::{load_program, Interpreter, Isa};
 
-fn read_input() -> Vec<usize> {
-  let input = fs::read_to_string("inputs/day02.txt").expect("No input.txt found");
-  input.split(",")
-    .map(|op| op.parse::<usize>().unwrap())
-    .collect()
+fn read_input() -> Vec<i64> {
+  load_program("inputs/day02.txt").unwrap_or_else(|e| panic!("{}", e))
 }
 
-fn execute(noun: usize, verb: usize) -> usize {
-  let mut mem = read_input();
-  mem[1] = noun;
-  mem[2] = verb;
-
-  let mut iptr: usize = 0;
-  loop {
-    match mem[iptr] {
-      ADD => {
-        let addr_oprd1 = mem[iptr + 1];
-        let addr_oprd2 = mem[iptr + 2];
-        let addr_res = mem[iptr + 3];
-        mem[addr_res] = mem[addr_oprd1] + mem[addr_oprd2];
-      },
-      MULT => {
-        let addr_oprd1 = mem[iptr + 1];
-        let addr_oprd2 = mem[iptr + 2];
-        let addr_res = mem[iptr + 3];
-        mem[addr_res] = mem[addr_oprd1] * mem[addr_oprd2];
-      },
-      HALT => break,
-      _ => (),
-    }
-    iptr += 4;
-  }
-
-  mem[0]
+fn execute(noun: i64, verb: i64) -> i64 {
+  let mut interpreter = Interpreter::new(read_input()).with_strict_isa(Isa::Day02);
+  interpreter.mem[1] = noun;
+  interpreter.mem[2] = verb;
+  interpreter.run_to_halt().expect("Intcode program failed!");
+  interpreter.mem[0]
 }
 
 fn part1() {
@@ -1,170 +1,30 @@
-use std::collections::VecDeque;
-use std::fs;
-use std::cmp;
-
-const ADD_INS: i32 = 1;
-const ADD_SIZE: usize = 4;
-const MULT_INS: i32 = 2;
-const MULT_SIZE: usize = 4;
-const INP_INS: i32 = 3;
-const INP_SIZE: usize = 2;
-const OUT_INS: i32 = 4;
-const OUT_SIZE: usize = 2;
-const JMPT_INS: i32 = 5;
-const JMPT_SIZE: usize = 3;
-const JMPF_INS: i32 = 6;
-const JMPF_SIZE: usize = 3;
-const TLS_INS: i32 = 7;
-const TLS_SIZE: usize = 4;
-const TEQ_INS: i32 = 8;
-const TEQ_SIZE: usize = 4;
-const HALT_INS: i32 = 99;
-const HALT_SIZE: usize = 1; 
-
-fn read_input() -> Vec<i32> {
-  let input = fs::read_to_string("inputs/day07.txt").expect("No input.txt found");
-  input.split(",")
-    .map(|op| op.parse::<i32>().unwrap())
-    .collect()
-}
-
-const TRIMMERS: [i32; 10] = [1, 10, 100, 1000, 10000, 100000, 1000000, 10000000, 100000000, 1000000000];
-fn digit_at(n: i32, i: usize) -> i32 {
-  (n / TRIMMERS[i]) % 10
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum State {
-  Idle,
-  Running,
-  Interrupted,
-  Halted,
-}
+pub mod intcode;
 
-impl State {
-  fn can_continue(&self) -> bool {
-    match self {
-      State::Idle | State::Running => true,
-      State::Interrupted | State::Halted => false, 
-    }
-  }
-}
-
-#[derive(Debug, Clone)]
-struct Interpreter {
-  mem: Vec<i32>,
-  stdin: VecDeque<i32>,
-  stdout: Vec<i32>,
-
-  state: State,
-  iptr: usize,
+use std::cmp;
+use std::env;
+use std::sync::mpsc;
+use std::thread;
+use intcode::{load_program, Interpreter, Isa, Network, State};
+use intcode::network::Outcome;
+
+fn read_input() -> Vec<i64> {
+  load_program("inputs/day07.txt").unwrap_or_else(|e| panic!("{}", e))
 }
 
-const POSITION: i32 = 0;
-const IMMEDIATE: i32 = 1;
-
-impl Interpreter {
-  fn new(mem: Vec<i32>) -> Interpreter {
-    Interpreter {
-      mem: mem,
-      stdin: VecDeque::new(),
-      stdout: Vec::new(),
-      state: State::Idle,
-      iptr: 0,
-    }
-  }
-
-  fn get_param(&self, idx: usize, ofst: usize, modes: i32) -> i32 {
-    match digit_at(modes, ofst - 1) {
-      POSITION => self.mem[self.mem[idx + ofst] as usize],
-      IMMEDIATE => self.mem[idx + ofst],
-      _ => panic!(),
-    }
-  }
-
-  fn execute(&mut self) {
-    loop {
-      self.step();
-      if !self.state.can_continue() {
-        break;
-      }
-    }
-  }
-
-  fn step(&mut self) {
-    self.state = State::Running;
-
-    let ins = self.mem[self.iptr] % 100;
-    let modes = (self.mem[self.iptr] - ins) / 100;
-    match ins {
-      ADD_INS => {
-        let addr_res = self.mem[self.iptr + 3] as usize;
-        self.mem[addr_res] =
-          self.get_param(self.iptr, 1, modes) +
-          self.get_param(self.iptr, 2, modes);
-        self.iptr += ADD_SIZE;
-      },
-      MULT_INS => {
-        let addr_res = self.mem[self.iptr + 3] as usize;
-        self.mem[addr_res] =
-          self.get_param(self.iptr, 1, modes) *
-          self.get_param(self.iptr, 2, modes);
-        self.iptr += MULT_SIZE;
-      },
-      INP_INS => {
-        let addr_res = self.mem[self.iptr + 1] as usize;
-        match self.stdin.pop_front() {
-          Some(inp) => {
-            self.mem[addr_res] = inp;
-            self.iptr += INP_SIZE;
-          },
-          None => {
-            self.state = State::Interrupted;
-            return;
-          },
-        }
-      },
-      OUT_INS => {
-        self.stdout.push(self.get_param(self.iptr, 1, modes));
-        self.iptr += OUT_SIZE;
-      },
-      JMPT_INS => {
-        if self.get_param(self.iptr, 1, modes) != 0 {
-          self.iptr = self.get_param(self.iptr, 2, modes) as usize;
-        } else {
-          self.iptr += JMPT_SIZE;
-        }
-      },
-      JMPF_INS => {
-        if self.get_param(self.iptr, 1, modes) == 0 {
-          self.iptr = self.get_param(self.iptr, 2, modes) as usize;
-        } else {
-          self.iptr += JMPF_SIZE;
-        }
-      },
-      TLS_INS => {
-        let par1 = self.get_param(self.iptr, 1, modes);
-        let par2 = self.get_param(self.iptr, 2, modes);
-        let addr_res = self.mem[self.iptr + 3] as usize;
-        self.mem[addr_res] = if par1 < par2 { 1 } else { 0 };
-        self.iptr += TLS_SIZE;
-      },
-      TEQ_INS => {
-        let par1 = self.get_param(self.iptr, 1, modes);
-        let par2 = self.get_param(self.iptr, 2, modes);
-        let addr_res = self.mem[self.iptr + 3] as usize;
-        self.mem[addr_res] = if par1 == par2 { 1 } else { 0 };
-        self.iptr += TEQ_SIZE;
-      },
-      HALT_INS => {
-        self.state = State::Halted;
-        return;
-      },
-      _ => panic!("Invalid instruction {}!", ins),
-    };
+const AMPLIFIERS: [&str; 5] = ["A", "B", "C", "D", "E"];
 
-    self.state = State::Idle;
+// Amplifiers chained from A to E, with the initial signal queued for A
+fn amplifiers(opcodes: &[i64], phases: [i64; 5]) -> Network {
+  let mut network = Network::new();
+  for (name, phase) in AMPLIFIERS.iter().zip(phases.iter()) {
+    network.node(name, Interpreter::new(opcodes.to_vec()).with_strict_isa(Isa::Day05)).input(name, *phase);
   }
+  network.input("A", 0)
+    .edge("A", "B")
+    .edge("B", "C")
+    .edge("C", "D")
+    .edge("D", "E");
+  network
 }
 
 fn part1() {
@@ -188,15 +48,12 @@ fn part1() {
               continue;
             }
 
-            let phases = [i, j, k, m, n];
-            let mut signal = 0;
-            for ii in 0..5 {
-              let mut interpreter = Interpreter::new(opcodes.clone());
-              interpreter.stdin.push_back(phases[ii]);
-              interpreter.stdin.push_back(signal);
-              interpreter.execute();
-              signal = *interpreter.stdout.last().expect("No output from amplifier program!");
+            let mut network = amplifiers(&opcodes, [i, j, k, m, n]);
+            match network.run().expect("Amplifier program failed!") {
+              Outcome::Halted => (),
+              outcome => panic!("Amplifiers stopped with {:?}!", outcome),
             }
+            let signal = *network.interpreter("E").stdout.back().expect("No output from amplifier program!");
             max = cmp::max(max, signal);
           }
         }
@@ -206,7 +63,64 @@ fn part1() {
   println!("{}", max);
 }
 
-fn part2() {
+// Runs the amplifiers round-robin on the current thread until E halts
+fn feedback_loop(opcodes: &[i64], phases: [i64; 5]) -> i64 {
+  let mut network = amplifiers(opcodes, phases);
+  network.edge("E", "A");
+  match network.run().expect("Amplifier program failed!") {
+    Outcome::Halted => (),
+    outcome => panic!("Amplifiers stopped with {:?}!", outcome),
+  }
+  // A halted before E's final output reached it
+  network.last_output("E").expect("No output from amplifier program!")
+}
+
+// Runs every amplifier on its own thread, blocking on a channel for input.
+// E's outputs pass through the current thread on their way back to A, the
+// last one seen before E halts is the final signal.
+fn feedback_loop_threaded(opcodes: &[i64], phases: [i64; 5]) -> i64 {
+  let (to_first, first_rx) = mpsc::channel();
+  let (tap_tx, tap_rx) = mpsc::channel();
+  // Amplifier `idx` reads from `receivers[idx]` and writes to `senders[idx]`
+  let mut senders = Vec::new();
+  let mut receivers = vec![first_rx];
+  for _ in 1..phases.len() {
+    let (tx, rx) = mpsc::channel();
+    senders.push(tx);
+    receivers.push(rx);
+  }
+  senders.push(tap_tx);
+
+  let mut handles = Vec::new();
+  for ((phase, rx), tx) in phases.iter().zip(receivers).zip(senders) {
+    let mut interpreter = Interpreter::new(opcodes.to_vec()).with_strict_isa(Isa::Day05);
+    interpreter.stdin.push_back(*phase);
+    interpreter.input = Some(Box::new(rx));
+    interpreter.output = Some(Box::new(tx));
+    handles.push(thread::spawn(move || {
+      match interpreter.execute().expect("Amplifier program failed!") {
+        State::Halted => (),
+        state => panic!("Amplifier program stopped in state {:?}!", state),
+      }
+    }));
+  }
+
+  let mut signal = 0;
+  let _ = to_first.send(signal);
+  for out in tap_rx {
+    signal = out;
+    // A may already have halted
+    let _ = to_first.send(out);
+  }
+  drop(to_first);
+
+  for handle in handles {
+    handle.join().expect("Amplifier thread panicked!");
+  }
+  signal
+}
+
+fn search_feedback_loop<F: Fn(&[i64], [i64; 5]) -> i64>(run: F) -> i64 {
   let opcodes = read_input();
   let mut max = 0;
   for i in 5..10 {
@@ -226,45 +140,51 @@ fn part2() {
             if i == n || j == n || k == n || m == n {
               continue;
             }
-
-            let phases = [i, j, k, m, n];
-            let mut instances = vec![Interpreter::new(opcodes.clone()); 5];
-            let last_idx = instances.len() - 1;
-
-            let mut idx = 0;
-            let mut signal = 0;
-            loop {
-              let interpreter = &mut instances[idx];
-              // First time running this instance
-              if interpreter.state == State::Idle {
-                interpreter.stdin.push_back(phases[idx]);
-              }
-              interpreter.stdin.push_back(signal);
-
-              // Execute the program until halt or interrupt
-              interpreter.execute();
-              // Even though we don't explicitly wait for outputs, it is guaranteed (AoC description)
-              // that the program will output a signal before it requests the next input signal
-              match interpreter.state {
-                // When the last amplifier halts, all other amplifiers must halted too
-                // therefore we can safely take the final output now
-                State::Halted if idx == last_idx => break,
-                State::Halted | State::Interrupted => signal = *interpreter.stdout.last().expect("No output from amplifier program!"),
-                _ => panic!("Invalid state after executing to halt or interrupted!"),
-              }
-              idx = if idx + 1 < instances.len() { idx + 1 } else { 0 };
-            }
-            
-            max = cmp::max(max, signal);
+            max = cmp::max(max, run(&opcodes, [i, j, k, m, n]));
           }
         }
       }
     }
   }
-  println!("{}", max);
+  max
+}
+
+fn part2() {
+  println!("{}", search_feedback_loop(feedback_loop));
 }
 
+fn part2_threaded() {
+  println!("{}", search_feedback_loop(feedback_loop_threaded));
+}
+
+// `--threaded` runs every amplifier on its own thread
 fn main() {
   // part1();
-  part2();
+  if env::args().any(|arg| arg == "--threaded") {
+    part2_threaded();
+  } else {
+    part2();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const EXAMPLES: [(&str, [i64; 5], i64); 2] = [
+    ("3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+      [9, 8, 7, 6, 5], 139629729),
+    ("3,52,1001,52,-5,52,3,53,1,52,56,54,1007,54,5,55,1005,55,26,1001,54,-5,54,1105,1,12,1,53,54,53,\
+      1008,54,0,55,1001,55,1,55,2,53,55,53,4,53,1001,56,-1,56,1005,56,6,99,0,0,0,0,10",
+      [9, 7, 8, 5, 6], 18216),
+  ];
+
+  #[test]
+  fn threaded_matches_round_robin() {
+    for &(program, phases, signal) in EXAMPLES.iter() {
+      let opcodes = intcode::parse_program("example", program).unwrap();
+      assert_eq!(feedback_loop(&opcodes, phases), signal);
+      assert_eq!(feedback_loop_threaded(&opcodes, phases), signal);
+    }
+  }
 }
@@ -1,210 +1,13 @@
-use std::collections::{HashSet, VecDeque};
-use std::fs;
-use std::ops;
+pub mod intcode;
 
-const ADD_INS: i64 = 1;
-const ADD_SIZE: usize = 4;
-const MULT_INS: i64 = 2;
-const MULT_SIZE: usize = 4;
-const INP_INS: i64 = 3;
-const INP_SIZE: usize = 2;
-const OUT_INS: i64 = 4;
-const OUT_SIZE: usize = 2;
-const JMPT_INS: i64 = 5;
-const JMPT_SIZE: usize = 3;
-const JMPF_INS: i64 = 6;
-const JMPF_SIZE: usize = 3;
-const TLS_INS: i64 = 7;
-const TLS_SIZE: usize = 4;
-const TEQ_INS: i64 = 8;
-const TEQ_SIZE: usize = 4;
-const SRL_INS: i64 = 9;
-const SRL_SIZE: usize = 2;
-const HALT_INS: i64 = 99;
-const HALT_SIZE: usize = 1; 
+use std::collections::HashSet;
+use std::env;
+use std::ops;
+use intcode::{load_program, Interpreter, Isa, State};
+use intcode::debugger::Debugger;
 
 fn read_input() -> Vec<i64> {
-  let input = fs::read_to_string("inputs/day11.txt").expect("No input.txt found");
-  input.split(",")
-    .map(|op| op.parse::<i64>().unwrap())
-    .collect()
-}
-
-const TRIMMERS: [i64; 10] = [1, 10, 100, 1000, 10000, 100000, 1000000, 10000000, 100000000, 1000000000];
-fn digit_at(n: i64, i: usize) -> i64 {
-  (n / TRIMMERS[i]) % 10
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum State {
-  Idle,
-  Running,
-  Interrupted,
-  Halted,
-}
-
-impl State {
-  fn can_continue(&self) -> bool {
-    match self {
-      State::Idle | State::Running => true,
-      State::Interrupted | State::Halted => false, 
-    }
-  }
-}
-
-#[derive(Debug, Clone)]
-struct Interpreter {
-  mem: Vec<i64>,
-  stdin: VecDeque<i64>,
-  stdout: VecDeque<i64>,
-
-  state: State,
-  iptr: usize,
-  rptr: usize,
-}
-
-const POSITION: i64 = 0;
-const IMMEDIATE: i64 = 1;
-const RELATIVE: i64 = 2;
-
-impl Interpreter {
-  fn new(mem: Vec<i64>) -> Interpreter {
-    Interpreter {
-      mem: mem,
-      stdin: VecDeque::new(),
-      stdout: VecDeque::new(),
-      state: State::Idle,
-      iptr: 0,
-      rptr: 0,
-    }
-  }
-
-  fn get_param(&self, ofst: usize, modes: i64) -> i64 {
-    match digit_at(modes, ofst - 1) {
-      POSITION => {
-        let addr = self.mem[self.iptr + ofst];
-        self.mem[addr as usize]
-      },
-      IMMEDIATE => self.mem[self.iptr + ofst],
-      RELATIVE => {
-        // Preserve sign for computation of the offset and address
-        let rptr_ofst = self.mem[self.iptr + ofst];
-        let addr = self.rptr as i64 + rptr_ofst;
-        self.mem[addr as usize]
-      },
-      _ => panic!(),
-    }
-  }
-
-  fn get_addr(&self, ofst: usize, modes: i64) -> usize {
-    match digit_at(modes, ofst - 1) {
-      POSITION => self.mem[self.iptr + ofst] as usize,
-      RELATIVE => {
-        let rptr_ofst = self.mem[self.iptr + ofst];
-        let addr = self.rptr as i64 + rptr_ofst;
-        addr as usize
-      },
-      _ => panic!(),
-    }
-  }
-
-  fn execute(&mut self) {
-    loop {
-      self.step();
-      if !self.state.can_continue() {
-        break;
-      }
-    }
-  }
-
-  fn step(&mut self) {
-    self.state = State::Running;
-
-    let ins = self.mem[self.iptr] % 100;
-    let modes = (self.mem[self.iptr] - ins) / 100;
-    match ins {
-      ADD_INS => {
-        let addr_res = self.get_addr(3, modes);
-        self.mem[addr_res] =
-          self.get_param(1, modes) +
-          self.get_param(2, modes);
-        self.iptr += ADD_SIZE;
-      },
-      MULT_INS => {
-        let addr_res = self.get_addr(3, modes);
-        self.mem[addr_res] =
-          self.get_param(1, modes) *
-          self.get_param(2, modes);
-        self.iptr += MULT_SIZE;
-      },
-      INP_INS => {
-        let addr_res = self.get_addr(1, modes);
-        match self.stdin.pop_front() {
-          Some(inp) => {
-            self.mem[addr_res] = inp;
-            self.iptr += INP_SIZE;
-          },
-          None => {
-            self.state = State::Interrupted;
-            return;
-          },
-        }
-      },
-      OUT_INS => {
-        self.stdout.push_front(self.get_param(1, modes));
-        self.iptr += OUT_SIZE;
-      },
-      JMPT_INS => {
-        if self.get_param(1, modes) == 1 {
-          self.iptr = self.get_param(2, modes) as usize;
-        } else {
-          self.iptr += JMPT_SIZE;
-        }
-      },
-      JMPF_INS => {
-        if self.get_param(1, modes) == 0 {
-          self.iptr = self.get_param(2, modes) as usize;
-        } else {
-          self.iptr += JMPF_SIZE;
-        }
-      },
-      TLS_INS => {
-        let par1 = self.get_param(1, modes);
-        let par2 = self.get_param(2, modes);
-        let addr_res = self.get_addr(3, modes);
-        self.mem[addr_res] = if par1 < par2 { 1 } else { 0 };
-        self.iptr += TLS_SIZE;
-      },
-      TEQ_INS => {
-        let par1 = self.get_param(1, modes);
-        let par2 = self.get_param(2, modes);
-        let addr_res = self.get_addr(3, modes);
-        self.mem[addr_res] = if par1 == par2 { 1 } else { 0 };
-        self.iptr += TEQ_SIZE;
-      },
-      SRL_INS => {
-        let i = self.get_param(1, modes);
-        let new_rptr = self.rptr as i64 + i;
-        self.rptr = new_rptr as usize;
-        self.iptr += SRL_SIZE;
-      },
-      HALT_INS => {
-        self.state = State::Halted;
-        return;
-      },
-      _ => panic!("Invalid instruction {}!", ins),
-    };
-
-    self.state = State::Idle;
-  }
-
-  fn try_pop_output(&mut self) -> Option<i64> {
-    self.stdout.pop_back()
-  }
-
-  fn pop_output(&mut self) -> i64 {
-    self.stdout.pop_back().expect("No output from program!")
-  }
+  load_program("inputs/day11.txt").unwrap_or_else(|e| panic!("{}", e))
 }
 
 #[derive(PartialEq, Eq, Clone, Copy)]
@@ -272,6 +75,8 @@ struct Turtle {
   pos: Vector2i,
   dir: Vector2i,
   controller: Interpreter,
+  // Stops the controller program on breakpoints, see `main`
+  debugger: Option<Debugger>,
 }
 
 const LEFT: i64 = 0;
@@ -294,7 +99,8 @@ impl Turtle {
         y: height as i64 / 2
       },
       dir: Vector2i { x: 0, y: 1 }, // Facing up
-      controller: Interpreter::new(mem),
+      controller: Interpreter::new(mem).with_strict_isa(Isa::Day09),
+      debugger: None,
     }
   }
 
@@ -304,27 +110,42 @@ impl Turtle {
       return;
     }
 
+    // Outputs come in pairs, the color to paint and then the way to turn
+    let mut color = None;
     loop {
-      self.controller.step();
-      match self.controller.state {
-        State::Halted => return,
-        State::Interrupted => {
-          let idx = self.pos.to_idx(&self.canvas);
-          self.controller.stdin.push_back(self.canvas[idx]);
-        },
-        State::Idle => if self.controller.stdout.len() >= 2 {
-          let idx = self.pos.to_idx(&self.canvas);
+      if let Some(ref mut debugger) = self.debugger {
+        if !debugger.hook(&mut self.controller).expect("Debugger I/O failed!") {
+          return;
+        }
+      }
+
+      let canvas = &mut self.canvas;
+      let pos = &mut self.pos;
+      let dir = &mut self.dir;
+      let mut on_output = |val: i64| match color.take() {
+        None => color = Some(val),
+        Some(c) => {
+          let idx = pos.to_idx(canvas);
           // Paint the canvas
-          self.canvas[idx] = self.controller.pop_output();
+          canvas[idx] = c;
           // Rotate the robot
-          let op = self.controller.pop_output();
-          self.dir = rotate_vec(&self.dir, op);
+          *dir = rotate_vec(dir, val);
           // Move the robot
-          self.pos += self.dir;
+          *pos += *dir;
 
           on_paint(idx);
         },
+      };
+      self.controller.step_with(None, Some(&mut on_output)).expect("Controller program failed!");
+      match self.controller.state {
+        State::Halted => return,
+        State::AwaitingInput => {
+          let idx = self.pos.to_idx(&self.canvas);
+          self.controller.stdin.push_back(self.canvas[idx]);
+        },
+        State::Idle | State::OutputReady(_) => (),
         State::Running => panic!("Interpretere didn't finish running!"),
+        State::OutOfFuel => panic!("Controller program ran out of fuel!"),
       }
     }
   }
@@ -360,12 +181,7 @@ fn print_canvas(canvas: &Canvas) {
 }
 
 fn part1() {
-  let mut opcodes = read_input();
-  for _ in 0..1024 {
-    opcodes.push(0);
-  }
-  
-  let mut turtle = Turtle::new(opcodes, 128, 128);
+  let mut turtle = Turtle::new(read_input(), 128, 128);
   let mut painted_pos = HashSet::new();
   turtle.execute(|i| {
     painted_pos.insert(i);
@@ -375,13 +191,9 @@ fn part1() {
   println!("{}", painted_pos.len());
 }
 
-fn part2() {
-  let mut opcodes = read_input();
-  for _ in 0..1024 {
-    opcodes.push(0);
-  }
-  
-  let mut turtle = Turtle::new(opcodes, 128, 128);
+fn part2(debugger: Option<Debugger>) {
+  let mut turtle = Turtle::new(read_input(), 128, 128);
+  turtle.debugger = debugger;
 
   // Start on a white panel
   let middle = 128 / 2;
@@ -392,7 +204,15 @@ fn part2() {
   print_canvas(&turtle.canvas);
 }
 
+// `--debug [script]` runs the controller program under the debugger, reading
+// commands from the script or the terminal
 fn main() {
+  let args: Vec<String> = env::args().skip(1).collect();
+  let debugger = args.iter().position(|arg| arg == "--debug").map(|idx| match args.get(idx + 1) {
+    Some(script) => Debugger::from_script(script).expect("Cannot read script!"),
+    None => Debugger::interactive(),
+  });
+
   // part1();
-  part2();
+  part2(debugger);
 }
@@ -0,0 +1,21 @@
+pub mod intcode;
+
+use std::env;
+use intcode::{load_machine, Journal};
+use intcode::debugger::Debugger;
+
+// Takes either a program or a machine snapshot
+fn main() {
+  let args: Vec<String> = env::args().collect();
+  if args.len() < 2 {
+    panic!("Usage: debugger <inputs/dayNN.txt | snapshot> [script]");
+  }
+
+  let mut interpreter = load_machine(&args[1]).unwrap_or_else(|e| panic!("{}", e));
+  interpreter.journal = Some(Journal::new(1_000_000));
+  let mut debugger = match args.get(2) {
+    Some(script) => Debugger::from_script(script).expect("Cannot read script!"),
+    None => Debugger::interactive(),
+  };
+  debugger.run(&mut interpreter).expect("Debugger I/O failed!");
+}
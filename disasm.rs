@@ -0,0 +1,11 @@
+pub mod intcode;
+
+use std::env;
+use intcode::{disasm, load_machine};
+
+// Takes either a program or a machine snapshot
+fn main() {
+  let path = env::args().nth(1).expect("Usage: disasm <inputs/dayNN.txt | snapshot>");
+  let interpreter = load_machine(&path).unwrap_or_else(|e| panic!("{}", e));
+  print!("{}", disasm::listing_mem(&interpreter.mem));
+}
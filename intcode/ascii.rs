@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+
+use super::{InputSource, Interpreter, OutputSink, Result, State};
+
+// Output of a text-driven program, split at newlines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Text {
+  // Without the newline
+  Line(String),
+  // Not a character code, e.g. a puzzle answer after the text
+  Value(i64),
+}
+
+pub fn is_ascii(val: i64) -> bool {
+  (0..=127).contains(&val)
+}
+
+// Character codes of `line` followed by a newline, None if it has characters
+// outside ASCII, `decode` would read those back as values
+pub fn encode_line(line: &str) -> Option<Vec<i64>> {
+  if !line.is_ascii() {
+    return None;
+  }
+  Some(line.bytes().map(|c| c as i64).chain(Some('\n' as i64)).collect())
+}
+
+// Splits outputs into lines. Text not ended by a newline yet, e.g. a prompt,
+// is a line too.
+pub fn decode(vals: &[i64]) -> Vec<Text> {
+  let mut texts = Vec::new();
+  let mut line = String::new();
+  let mut pending = false;
+  for &val in vals {
+    if !is_ascii(val) {
+      if pending {
+        texts.push(Text::Line(line.split_off(0)));
+        pending = false;
+      }
+      texts.push(Text::Value(val));
+    } else if val == '\n' as i64 {
+      texts.push(Text::Line(line.split_off(0)));
+      pending = false;
+    } else {
+      line.push(val as u8 as char);
+      pending = true;
+    }
+  }
+  if pending {
+    texts.push(Text::Line(line));
+  }
+  texts
+}
+
+impl Interpreter {
+  // Queues `line` on `stdin` as character codes, ended by a newline
+  pub fn push_line(&mut self, line: &str) {
+    match encode_line(line) {
+      Some(vals) => self.stdin.extend(vals),
+      None => panic!("Input line {:?} isn't ASCII!", line),
+    }
+  }
+
+  // Takes everything from `stdout` as text
+  pub fn pop_text(&mut self) -> Vec<Text> {
+    let vals = self.stdout.drain(..).collect::<Vec<_>>();
+    decode(&vals)
+  }
+
+  // Runs with the terminal as input and output until the program halts or
+  // the terminal closes, which leaves it `AwaitingInput`
+  pub fn run_ascii_terminal(&mut self) -> Result<State> {
+    let mut input = AsciiTerminal::new();
+    let mut output = AsciiTerminal::new();
+    self.execute_with(Some(&mut input), Some(&mut output))
+  }
+}
+
+// Reads input a line at a time from the terminal and prints outputs as they
+// come, values that aren't character codes on a line of their own. Lines
+// that aren't ASCII are asked for again.
+#[derive(Default)]
+pub struct AsciiTerminal {
+  pending: VecDeque<i64>,
+}
+
+impl AsciiTerminal {
+  pub fn new() -> AsciiTerminal {
+    AsciiTerminal::default()
+  }
+}
+
+impl InputSource for AsciiTerminal {
+  fn read(&mut self) -> Option<i64> {
+    while self.pending.is_empty() {
+      let mut line = String::new();
+      match io::stdin().lock().read_line(&mut line) {
+        Ok(0) | Err(_) => return None,
+        Ok(_) => (),
+      }
+      match encode_line(line.trim_end_matches(['\n', '\r'])) {
+        Some(vals) => self.pending.extend(vals),
+        None => eprintln!("only ASCII characters, please"),
+      }
+    }
+    self.pending.pop_front()
+  }
+}
+
+impl OutputSink for AsciiTerminal {
+  fn write(&mut self, val: i64) {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if is_ascii(val) {
+      let _ = out.write_all(&[val as u8]);
+    } else {
+      let _ = writeln!(out, "{}", val);
+    }
+    let _ = out.flush();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn encode() {
+    assert_eq!(encode_line("hi"), Some(vec![104, 105, 10]));
+    assert_eq!(encode_line(""), Some(vec![10]));
+    assert_eq!(encode_line("hé"), None);
+  }
+
+  #[test]
+  fn decode_lines_and_values() {
+    let mut vals = encode_line("ok").unwrap();
+    vals.extend(vec![62, 32, 1234, 10, 63]);
+    assert_eq!(decode(&vals), vec![
+      Text::Line("ok".to_string()),
+      // The prompt before the value ends there
+      Text::Line("> ".to_string()),
+      Text::Value(1234),
+      Text::Line(String::new()),
+      Text::Line("?".to_string()),
+    ]);
+    assert_eq!(decode(&[-1, 128]), vec![Text::Value(-1), Text::Value(128)]);
+  }
+
+  #[test]
+  fn push_and_pop() {
+    let mut vm = Interpreter::new(vec![3, 9, 4, 9, 3, 9, 4, 9, 99, 0]);
+    vm.push_line("a");
+    vm.run_to_halt().unwrap();
+    assert_eq!(vm.pop_text(), vec![Text::Line("a".to_string())]);
+  }
+}
@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use super::{IMMEDIATE, POSITION, RELATIVE};
+use super::disasm::{self, OpInfo, Role};
+
+// Source syntax, one statement per line:
+//
+//   loop:  add [counter], #1, [counter]   ; position, immediate
+//          out [rb-2]                      ; relative
+//          jmpt #1, #loop
+//          halt
+//   counter: .data 0, 5, loop+2
+//
+// Values are numbers, labels or `label+n`/`label-n`. `;` starts a comment.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+  pub line: usize,
+  pub msg: String,
+}
+
+impl fmt::Display for AsmError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "line {}: {}", self.line, self.msg)
+  }
+}
+
+impl error::Error for AsmError {}
+
+#[derive(Debug, Clone)]
+enum Value {
+  Num(i64),
+  Label(String, i64),
+}
+
+#[derive(Debug)]
+enum Stmt {
+  Ins { op: &'static OpInfo, operands: Vec<(i64, Value)> },
+  Data { values: Vec<Value> },
+}
+
+struct Parsed {
+  line: usize,
+  stmt: Stmt,
+}
+
+fn err<T>(line: usize, msg: String) -> Result<T, AsmError> {
+  Err(AsmError { line: line, msg: msg })
+}
+
+fn is_label(s: &str) -> bool {
+  let mut chars = s.chars();
+  match chars.next() {
+    Some(c) if c.is_ascii_alphabetic() || c == '_' => (),
+    _ => return false,
+  }
+  s != "rb" && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_value(line: usize, s: &str) -> Result<Value, AsmError> {
+  let s = s.trim();
+  if let Ok(n) = s.parse::<i64>() {
+    return Ok(Value::Num(n));
+  }
+  let (name, ofst) = match s.find(['+', '-']) {
+    Some(i) => match s[i..].replace("+", "").trim().parse::<i64>() {
+      Ok(ofst) => (s[..i].trim(), ofst),
+      Err(_) => return err(line, format!("invalid offset in `{}`", s)),
+    },
+    None => (s, 0),
+  };
+  if !is_label(name) {
+    return err(line, format!("invalid value `{}`", s));
+  }
+  Ok(Value::Label(name.to_string(), ofst))
+}
+
+fn parse_operand(line: usize, s: &str) -> Result<(i64, Value), AsmError> {
+  let s = s.trim();
+  if let Some(val) = s.strip_prefix('#') {
+    return Ok((IMMEDIATE, parse_value(line, val)?));
+  }
+  if s.starts_with('[') && s.ends_with(']') {
+    let inner = s[1..s.len() - 1].trim();
+    // `rb` alone or followed by an offset, labels like `rbase` are positions
+    let relative = inner == "rb" ||
+      (inner.starts_with("rb") && inner[2..].starts_with(|c: char| c == '+' || c == '-' || c.is_whitespace()));
+    if relative {
+      let ofst = inner[2..].trim();
+      if ofst.is_empty() {
+        return Ok((RELATIVE, Value::Num(0)));
+      }
+      if !ofst.starts_with('+') && !ofst.starts_with('-') {
+        return err(line, format!("invalid relative operand `{}`", s));
+      }
+      let val = match parse_value(line, &ofst[1..])? {
+        Value::Num(n) if ofst.starts_with('-') => Value::Num(-n),
+        Value::Num(n) => Value::Num(n),
+        _ => return err(line, format!("relative offset must be a number in `{}`", s)),
+      };
+      return Ok((RELATIVE, val));
+    }
+    return Ok((POSITION, parse_value(line, inner)?));
+  }
+  err(line, format!("invalid operand `{}`, expected `[addr]`, `#value` or `[rb+n]`", s))
+}
+
+fn parse_stmt(line: usize, text: &str) -> Result<Stmt, AsmError> {
+  let (head, rest) = match text.find(char::is_whitespace) {
+    Some(i) => (&text[..i], text[i..].trim()),
+    None => (text, ""),
+  };
+  let args: Vec<&str> = if rest.is_empty() { Vec::new() } else { rest.split(',').collect() };
+
+  if head == ".data" {
+    if args.is_empty() {
+      return err(line, ".data needs at least one value".to_string());
+    }
+    let values = args.iter()
+      .map(|arg| parse_value(line, arg))
+      .collect::<Result<Vec<_>, _>>()?;
+    return Ok(Stmt::Data { values: values });
+  }
+  if head.starts_with('.') {
+    return err(line, format!("unknown directive `{}`", head));
+  }
+
+  let op = match disasm::opcode_by_name(&head.to_lowercase()) {
+    Some(op) => op,
+    None => return err(line, format!("unknown mnemonic `{}`", head)),
+  };
+  if args.len() != op.params.len() {
+    return err(line, format!("`{}` takes {} operands, got {}", op.name, op.params.len(), args.len()));
+  }
+  let mut operands = Vec::new();
+  for (i, (arg, role)) in args.iter().zip(op.params.iter()).enumerate() {
+    let operand = parse_operand(line, arg)?;
+    if operand.0 == IMMEDIATE && *role == Role::Write {
+      return err(line, format!("operand {} of `{}` is written to and cannot be immediate", i + 1, op.name));
+    }
+    operands.push(operand);
+  }
+  Ok(Stmt::Ins { op: op, operands: operands })
+}
+
+fn resolve(line: usize, val: &Value, labels: &HashMap<String, usize>) -> Result<i64, AsmError> {
+  match val {
+    Value::Num(n) => Ok(*n),
+    Value::Label(name, ofst) => match labels.get(name) {
+      Some(addr) => Ok(*addr as i64 + ofst),
+      None => err(line, format!("undefined label `{}`", name)),
+    },
+  }
+}
+
+pub fn assemble(src: &str) -> Result<Vec<i64>, AsmError> {
+  // First pass: parse statements and assign label addresses
+  let mut parsed = Vec::new();
+  let mut labels = HashMap::new();
+  let mut addr = 0;
+  for (i, raw) in src.lines().enumerate() {
+    let line = i + 1;
+    let mut text = match raw.find(';') {
+      Some(i) => &raw[..i],
+      None => raw,
+    }.trim();
+
+    while let Some(i) = text.find(':') {
+      let name = text[..i].trim();
+      if !is_label(name) {
+        return err(line, format!("invalid label `{}`", name));
+      }
+      if labels.insert(name.to_string(), addr).is_some() {
+        return err(line, format!("duplicate label `{}`", name));
+      }
+      text = text[i + 1..].trim();
+    }
+    if text.is_empty() {
+      continue;
+    }
+
+    let stmt = parse_stmt(line, text)?;
+    addr += match &stmt {
+      Stmt::Ins { op, .. } => op.size(),
+      Stmt::Data { values } => values.len(),
+    };
+    parsed.push(Parsed { line: line, stmt: stmt });
+  }
+
+  // Second pass: emit cells with labels resolved
+  let mut out = Vec::new();
+  for Parsed { line, stmt } in parsed {
+    match stmt {
+      Stmt::Ins { op, operands } => {
+        let mut ins = op.code;
+        let mut scale = 100;
+        let mut vals = Vec::new();
+        for (mode, val) in operands {
+          ins += mode * scale;
+          scale *= 10;
+          vals.push(resolve(line, &val, &labels)?);
+        }
+        out.push(ins);
+        out.extend(vals);
+      },
+      Stmt::Data { values } => for val in values {
+        out.push(resolve(line, &val, &labels)?);
+      },
+    }
+  }
+  Ok(out)
+}
+
+// Comma-separated form, as read by the days' `read_input`
+pub fn to_text(program: &[i64]) -> String {
+  program.iter()
+    .map(|val| val.to_string())
+    .collect::<Vec<_>>()
+    .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const QUINE: [i64; 16] = [109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+
+  #[test]
+  fn relative_operands() {
+    assert_eq!(assemble("out [rb]").unwrap(), vec![204, 0]);
+    assert_eq!(assemble("out [rb+3]").unwrap(), vec![204, 3]);
+    assert_eq!(assemble("out [rb - 2]").unwrap(), vec![204, -2]);
+    assert!(assemble("out [rb*2]").is_err());
+  }
+
+  #[test]
+  fn labels_starting_with_rb() {
+    let src = "out [rbase]\nout [rb_count+1]\nhalt\nrbase: .data 7\nrb_count: .data 8";
+    assert_eq!(assemble(src).unwrap(), vec![4, 5, 4, 7, 99, 7, 8]);
+  }
+
+  #[test]
+  fn round_trip() {
+    let src = "
+      start:  inp [rbase]
+              srl #5
+              add [rbase], #-1, [rb+2]
+              out [rb-3]
+              jmpf [rbase], #start
+              mult #2, [rb], [rbase]
+              halt
+      rbase:  .data 12, -4, start+1
+    ";
+    let program = assemble(src).unwrap();
+    assert_eq!(assemble(&disasm::source(&program)).unwrap(), program);
+    assert_eq!(assemble(&disasm::source(&QUINE)).unwrap(), QUINE.to_vec());
+  }
+
+  #[test]
+  fn data_that_does_not_decode() {
+    let program = vec![1105, 1, 4, 12345, 99, 30002, -7, 4];
+    assert_eq!(assemble(&disasm::source(&program)).unwrap(), program);
+  }
+}
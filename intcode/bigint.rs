@@ -0,0 +1,270 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+const BASE: u64 = 1 << 32;
+// Largest power of ten in a limb, for printing and parsing
+const DECIMAL_BASE: u32 = 1_000_000_000;
+const DECIMAL_DIGITS: usize = 9;
+
+// Signed integer of any size. The magnitude is stored in 32-bit limbs,
+// least significant first, without leading zero limbs. Zero has no limbs and
+// is never negative.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct BigInt {
+  negative: bool,
+  limbs: Vec<u32>,
+}
+
+impl BigInt {
+  pub fn zero() -> BigInt {
+    BigInt::default()
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.limbs.is_empty()
+  }
+
+  pub fn is_negative(&self) -> bool {
+    self.negative
+  }
+
+  fn from_limbs(negative: bool, mut limbs: Vec<u32>) -> BigInt {
+    while limbs.last() == Some(&0) {
+      limbs.pop();
+    }
+    BigInt {
+      negative: negative && !limbs.is_empty(),
+      limbs: limbs,
+    }
+  }
+
+  pub fn from_i64(val: i64) -> BigInt {
+    let mag = val.unsigned_abs();
+    BigInt::from_limbs(val < 0, vec![mag as u32, (mag >> 32) as u32])
+  }
+
+  pub fn to_i64(&self) -> Option<i64> {
+    if self.limbs.len() > 2 {
+      return None;
+    }
+    let mag = self.limbs.iter().rev().fold(0u64, |acc, &limb| (acc << 32) | limb as u64);
+    if self.negative {
+      if mag <= i64::MAX as u64 + 1 {
+        Some((mag as i64).wrapping_neg())
+      } else {
+        None
+      }
+    } else if mag <= i64::MAX as u64 {
+      Some(mag as i64)
+    } else {
+      None
+    }
+  }
+
+  pub fn add(&self, other: &BigInt) -> BigInt {
+    if self.negative == other.negative {
+      return BigInt::from_limbs(self.negative, add_mag(&self.limbs, &other.limbs));
+    }
+    match cmp_mag(&self.limbs, &other.limbs) {
+      Ordering::Less => BigInt::from_limbs(other.negative, sub_mag(&other.limbs, &self.limbs)),
+      _ => BigInt::from_limbs(self.negative, sub_mag(&self.limbs, &other.limbs)),
+    }
+  }
+
+  pub fn mul(&self, other: &BigInt) -> BigInt {
+    let mut limbs = vec![0u32; self.limbs.len() + other.limbs.len()];
+    for (i, &a) in self.limbs.iter().enumerate() {
+      let mut carry = 0u64;
+      for (j, &b) in other.limbs.iter().enumerate() {
+        let cur = limbs[i + j] as u64 + a as u64 * b as u64 + carry;
+        limbs[i + j] = cur as u32;
+        carry = cur >> 32;
+      }
+      limbs[i + other.limbs.len()] = carry as u32;
+    }
+    BigInt::from_limbs(self.negative != other.negative, limbs)
+  }
+
+  // Divides the magnitude by `div` in place, returns the remainder
+  fn div_small(limbs: &mut Vec<u32>, div: u32) -> u32 {
+    let mut rem = 0u64;
+    for limb in limbs.iter_mut().rev() {
+      let cur = (rem << 32) | *limb as u64;
+      *limb = (cur / div as u64) as u32;
+      rem = cur % div as u64;
+    }
+    while limbs.last() == Some(&0) {
+      limbs.pop();
+    }
+    rem as u32
+  }
+
+  // Multiplies the magnitude by `mul` and adds `add`
+  fn mul_add_small(limbs: &mut Vec<u32>, mul: u32, add: u32) {
+    let mut carry = add as u64;
+    for limb in limbs.iter_mut() {
+      let cur = *limb as u64 * mul as u64 + carry;
+      *limb = cur as u32;
+      carry = cur >> 32;
+    }
+    if carry > 0 {
+      limbs.push(carry as u32);
+    }
+  }
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+  let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+  let mut carry = 0u64;
+  for i in 0..a.len().max(b.len()) {
+    let cur = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+    sum.push(cur as u32);
+    carry = cur >> 32;
+  }
+  sum.push(carry as u32);
+  sum
+}
+
+// `a - b` for `a >= b`
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+  let mut diff = Vec::with_capacity(a.len());
+  let mut borrow = 0u64;
+  for (i, &limb) in a.iter().enumerate() {
+    let sub = *b.get(i).unwrap_or(&0) as u64 + borrow;
+    let cur = limb as u64;
+    if cur >= sub {
+      diff.push((cur - sub) as u32);
+      borrow = 0;
+    } else {
+      diff.push((cur + BASE - sub) as u32);
+      borrow = 1;
+    }
+  }
+  diff
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+  a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+impl Ord for BigInt {
+  fn cmp(&self, other: &BigInt) -> Ordering {
+    match (self.negative, other.negative) {
+      (false, true) => Ordering::Greater,
+      (true, false) => Ordering::Less,
+      (false, false) => cmp_mag(&self.limbs, &other.limbs),
+      (true, true) => cmp_mag(&other.limbs, &self.limbs),
+    }
+  }
+}
+
+impl PartialOrd for BigInt {
+  fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl From<i64> for BigInt {
+  fn from(val: i64) -> BigInt {
+    BigInt::from_i64(val)
+  }
+}
+
+impl fmt::Display for BigInt {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.is_zero() {
+      return write!(f, "0");
+    }
+    let mut limbs = self.limbs.clone();
+    let mut chunks = Vec::new();
+    while !limbs.is_empty() {
+      chunks.push(BigInt::div_small(&mut limbs, DECIMAL_BASE));
+    }
+    if self.negative {
+      write!(f, "-")?;
+    }
+    write!(f, "{}", chunks.pop().unwrap())?;
+    for chunk in chunks.iter().rev() {
+      write!(f, "{:0width$}", chunk, width = DECIMAL_DIGITS)?;
+    }
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseBigIntError;
+
+impl fmt::Display for ParseBigIntError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "invalid integer")
+  }
+}
+
+impl FromStr for BigInt {
+  type Err = ParseBigIntError;
+
+  fn from_str(s: &str) -> Result<BigInt, ParseBigIntError> {
+    let (negative, digits) = match s.as_bytes().first() {
+      Some(b'-') => (true, &s[1..]),
+      Some(b'+') => (false, &s[1..]),
+      _ => (false, s),
+    };
+    if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+      return Err(ParseBigIntError);
+    }
+    let mut limbs = Vec::new();
+    for c in digits.bytes() {
+      BigInt::mul_add_small(&mut limbs, 10, (c - b'0') as u32);
+    }
+    Ok(BigInt::from_limbs(negative, limbs))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn big(s: &str) -> BigInt {
+    s.parse().unwrap()
+  }
+
+  #[test]
+  fn add_mixed_signs() {
+    assert_eq!(BigInt::from(5).add(&BigInt::from(-7)), BigInt::from(-2));
+    assert_eq!(BigInt::from(-5).add(&BigInt::from(7)), BigInt::from(2));
+    assert_eq!(BigInt::from(-5).add(&BigInt::from(5)), BigInt::zero());
+    assert!(!BigInt::from(-5).add(&BigInt::from(5)).is_negative());
+    // Borrows across a limb
+    assert_eq!(big("18446744073709551616").add(&BigInt::from(-1)).to_string(), "18446744073709551615");
+    assert_eq!(big("-18446744073709551616").add(&BigInt::from(1)).to_string(), "-18446744073709551615");
+  }
+
+  #[test]
+  fn mul_mixed_signs() {
+    assert_eq!(BigInt::from(-3).mul(&BigInt::from(4)), BigInt::from(-12));
+    assert_eq!(BigInt::from(3).mul(&BigInt::from(-4)), BigInt::from(-12));
+    assert_eq!(BigInt::from(-3).mul(&BigInt::from(-4)), BigInt::from(12));
+    let zero = BigInt::from(-3).mul(&BigInt::zero());
+    assert!(zero.is_zero() && !zero.is_negative());
+  }
+
+  #[test]
+  fn i64_bounds() {
+    let min = BigInt::from(i64::MIN);
+    assert_eq!(min.to_string(), "-9223372036854775808");
+    assert_eq!(min.to_i64(), Some(i64::MIN));
+    assert_eq!(min.add(&BigInt::from(-1)).to_i64(), None);
+    assert_eq!(BigInt::from(i64::MAX).to_i64(), Some(i64::MAX));
+    assert_eq!(BigInt::from(i64::MAX).add(&BigInt::from(1)).to_i64(), None);
+    assert_eq!(big("-9223372036854775808"), min);
+  }
+
+  #[test]
+  fn factorial() {
+    let fact = (1..=30).fold(BigInt::from(1), |acc, n| acc.mul(&BigInt::from(n)));
+    assert_eq!(fact.to_string(), "265252859812191058636308480000000");
+    assert_eq!(big(&fact.to_string()), fact);
+    assert!(BigInt::from(i64::MAX) < fact);
+  }
+}
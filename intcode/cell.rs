@@ -0,0 +1,159 @@
+use std::fmt;
+
+use super::bigint::BigInt;
+
+// Value type of an `Interpreter`'s memory, i64 unless a program needs more
+// room, e.g. `Interpreter::<BigInt>::from_program(&program)`. The machine's
+// `Arithmetic` picks between the wrapping and the checked operations.
+pub trait Cell: Clone + Ord + fmt::Debug + fmt::Display + Send + Sync + 'static {
+  fn from_i64(val: i64) -> Self;
+  // Instructions, addresses and relative base offsets have to fit
+  fn to_i64(&self) -> Option<i64>;
+  fn checked_add(&self, other: &Self) -> Option<Self>;
+  fn checked_mul(&self, other: &Self) -> Option<Self>;
+  fn wrapping_add(&self, other: &Self) -> Self;
+  fn wrapping_mul(&self, other: &Self) -> Self;
+
+  fn is_zero(&self) -> bool {
+    *self == Self::from_i64(0)
+  }
+
+  // For errors, which record instructions as i64
+  fn saturating_to_i64(&self) -> i64 {
+    match self.to_i64() {
+      Some(val) => val,
+      None if *self < Self::from_i64(0) => i64::MIN,
+      None => i64::MAX,
+    }
+  }
+}
+
+impl Cell for i64 {
+  fn from_i64(val: i64) -> i64 {
+    val
+  }
+
+  fn to_i64(&self) -> Option<i64> {
+    Some(*self)
+  }
+
+  fn checked_add(&self, other: &i64) -> Option<i64> {
+    i64::checked_add(*self, *other)
+  }
+
+  fn checked_mul(&self, other: &i64) -> Option<i64> {
+    i64::checked_mul(*self, *other)
+  }
+
+  fn wrapping_add(&self, other: &i64) -> i64 {
+    i64::wrapping_add(*self, *other)
+  }
+
+  fn wrapping_mul(&self, other: &i64) -> i64 {
+    i64::wrapping_mul(*self, *other)
+  }
+}
+
+impl Cell for i128 {
+  fn from_i64(val: i64) -> i128 {
+    val as i128
+  }
+
+  fn to_i64(&self) -> Option<i64> {
+    if *self >= i64::MIN as i128 && *self <= i64::MAX as i128 {
+      Some(*self as i64)
+    } else {
+      None
+    }
+  }
+
+  fn checked_add(&self, other: &i128) -> Option<i128> {
+    i128::checked_add(*self, *other)
+  }
+
+  fn checked_mul(&self, other: &i128) -> Option<i128> {
+    i128::checked_mul(*self, *other)
+  }
+
+  fn wrapping_add(&self, other: &i128) -> i128 {
+    i128::wrapping_add(*self, *other)
+  }
+
+  fn wrapping_mul(&self, other: &i128) -> i128 {
+    i128::wrapping_mul(*self, *other)
+  }
+}
+
+// Never overflows, both modes are the same
+impl Cell for BigInt {
+  fn from_i64(val: i64) -> BigInt {
+    BigInt::from_i64(val)
+  }
+
+  fn to_i64(&self) -> Option<i64> {
+    BigInt::to_i64(self)
+  }
+
+  fn checked_add(&self, other: &BigInt) -> Option<BigInt> {
+    Some(self.add(other))
+  }
+
+  fn checked_mul(&self, other: &BigInt) -> Option<BigInt> {
+    Some(self.mul(other))
+  }
+
+  fn wrapping_add(&self, other: &BigInt) -> BigInt {
+    self.add(other)
+  }
+
+  fn wrapping_mul(&self, other: &BigInt) -> BigInt {
+    self.mul(other)
+  }
+
+  fn is_zero(&self) -> bool {
+    BigInt::is_zero(self)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::{Arithmetic, Interpreter, IntcodeError};
+  use super::*;
+
+  // Squares its input twice and outputs the result
+  const SQUARES: [i64; 13] = [3, 13, 2, 13, 13, 13, 2, 13, 13, 13, 4, 13, 99];
+
+  fn squares<C: Cell>(inp: C, arithmetic: Arithmetic) -> Result<Vec<C>, IntcodeError> {
+    let mut vm = Interpreter::<C>::from_program(&SQUARES);
+    vm.arithmetic = arithmetic;
+    vm.stdin.push_back(inp);
+    vm.run_to_halt()?;
+    Ok(vm.stdout.into_iter().collect())
+  }
+
+  #[test]
+  fn i64_overflow() {
+    let inp = 1i64 << 20;
+    assert_eq!(squares(inp, Arithmetic::Wrapping), Ok(vec![0]));
+    match squares(inp, Arithmetic::Checked) {
+      Err(IntcodeError::Overflow { iptr: 6, .. }) => (),
+      res => panic!("expected Overflow, got {:?}", res),
+    }
+  }
+
+  #[test]
+  fn wider_cells() {
+    assert_eq!(squares(1i128 << 20, Arithmetic::Checked), Ok(vec![1i128 << 80]));
+    assert_eq!(squares(BigInt::from(1 << 20), Arithmetic::Checked), Ok(vec!["1208925819614629174706176".parse().unwrap()]));
+  }
+
+  #[test]
+  fn address_out_of_range() {
+    let mut vm = Interpreter::<i128>::from_program(&[1, 0, 0, 0, 99]);
+    vm.mem.set(1, 1 << 80);
+    match vm.run_to_halt() {
+      Err(IntcodeError::OutOfRange { iptr: 0, .. }) => (),
+      res => panic!("expected OutOfRange, got {:?}", res),
+    }
+  }
+}
@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::disasm::{self, Instruction, Operand};
+use super::*;
+
+// Instructions found by following execution from address 0, through both
+// branches of every jump with a constant target. The fall-through of an
+// unconditional jump is followed too, that is where calls return to.
+pub fn reachable(program: &[i64]) -> BTreeMap<usize, Instruction> {
+  let mut found = BTreeMap::new();
+  let mut pending = vec![0];
+  while let Some(addr) = pending.pop() {
+    if found.contains_key(&addr) {
+      continue;
+    }
+    let ins = match disasm::decode(program, addr) {
+      Some(ins) => ins,
+      None => continue,
+    };
+    if let Some(target) = static_target(&ins) {
+      pending.push(target);
+    }
+    if ins.op.code != HALT_INS {
+      pending.push(addr + ins.size());
+    }
+    found.insert(addr, ins);
+  }
+  found
+}
+
+fn is_jump(ins: &Instruction) -> bool {
+  ins.op.code == JMPT_INS || ins.op.code == JMPF_INS
+}
+
+// Target of a jump with an immediate, non-negative target
+fn static_target(ins: &Instruction) -> Option<usize> {
+  match ins.operands.get(1) {
+    Some(&Operand::Immediate(target)) if is_jump(ins) && target >= 0 => Some(target as usize),
+    _ => None,
+  }
+}
+
+// Whether a jump always or never goes, from an immediate condition
+fn constant_condition(ins: &Instruction) -> Option<bool> {
+  match ins.operands[0] {
+    Operand::Immediate(val) => Some((val != 0) == (ins.op.code == JMPT_INS)),
+    _ => None,
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+  Jump(usize),
+  FallThrough(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+  pub start: usize,
+  pub instructions: Vec<(usize, Instruction)>,
+  pub edges: Vec<Edge>,
+  // Ends in a jump whose target is only known at runtime
+  pub dynamic: bool,
+}
+
+impl Block {
+  // One past the last cell of the block
+  pub fn end(&self) -> usize {
+    let (addr, ins) = self.instructions.last().unwrap();
+    addr + ins.size()
+  }
+}
+
+// Basic blocks of the instructions `reachable` finds, by start address.
+// Blocks start at address 0, at jump targets and after jumps, and end at a
+// jump, a halt, the start of another block or cells that don't decode.
+#[derive(Debug, Clone)]
+pub struct Cfg {
+  pub blocks: BTreeMap<usize, Block>,
+}
+
+impl Cfg {
+  pub fn recover(program: &[i64]) -> Cfg {
+    let found = reachable(program);
+    let mut leaders = BTreeSet::new();
+    leaders.insert(0);
+    for (addr, ins) in &found {
+      if is_jump(ins) {
+        leaders.extend(static_target(ins));
+        leaders.insert(addr + ins.size());
+      }
+    }
+
+    let mut blocks = BTreeMap::new();
+    for &start in leaders.iter().filter(|addr| found.contains_key(addr)) {
+      let mut block = Block {
+        start: start,
+        instructions: Vec::new(),
+        edges: Vec::new(),
+        dynamic: false,
+      };
+      let mut addr = start;
+      loop {
+        let ins = found[&addr].clone();
+        let next = addr + ins.size();
+        let jump = is_jump(&ins);
+        let halt = ins.op.code == HALT_INS;
+        if jump {
+          let condition = constant_condition(&ins);
+          match (static_target(&ins), ins.operands[1]) {
+            (Some(target), _) if condition != Some(false) && found.contains_key(&target) =>
+              block.edges.push(Edge::Jump(target)),
+            // A negative constant target faults, it isn't computed
+            (Some(_), _) | (None, Operand::Immediate(_)) => (),
+            (None, _) => block.dynamic = condition != Some(false),
+          }
+          if condition != Some(true) && found.contains_key(&next) {
+            block.edges.push(Edge::FallThrough(next));
+          }
+        } else if !halt && found.contains_key(&next) && leaders.contains(&next) {
+          block.edges.push(Edge::FallThrough(next));
+        }
+        block.instructions.push((addr, ins));
+        if jump || halt || leaders.contains(&next) || !found.contains_key(&next) {
+          break;
+        }
+        addr = next;
+      }
+      blocks.insert(start, block);
+    }
+    Cfg { blocks: blocks }
+  }
+
+  // Addresses of the jumps with a computed target
+  pub fn dynamic_jumps(&self) -> Vec<usize> {
+    self.blocks.values()
+      .filter(|block| block.dynamic)
+      .map(|block| block.instructions.last().unwrap().0)
+      .collect()
+  }
+
+  // Graphviz source, one box per block with its disassembly. Jump edges are
+  // solid, fall-through edges dashed and blocks ending in a dynamic jump red.
+  pub fn to_dot(&self) -> String {
+    let mut out = String::from("digraph intcode {\n");
+    out.push_str("  node [shape=box, fontname=\"monospace\"];\n");
+    for block in self.blocks.values() {
+      let mut label = String::new();
+      for (addr, ins) in &block.instructions {
+        label.push_str(&format!("{}: {}\\l", addr, escape(&ins.to_string())));
+      }
+      if block.dynamic {
+        label.push_str("-> computed target\\l");
+        out.push_str(&format!("  b{} [label=\"{}\", color=red];\n", block.start, label));
+      } else {
+        out.push_str(&format!("  b{} [label=\"{}\"];\n", block.start, label));
+      }
+    }
+    for block in self.blocks.values() {
+      for edge in &block.edges {
+        match *edge {
+          Edge::Jump(target) => out.push_str(&format!("  b{} -> b{};\n", block.start, target)),
+          Edge::FallThrough(next) => out.push_str(&format!("  b{} -> b{} [style=dashed];\n", block.start, next)),
+        }
+      }
+    }
+    out.push_str("}\n");
+    out
+  }
+}
+
+fn escape(text: &str) -> String {
+  text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  //  0: inp [30]
+  //  2: jmpt [30], #9
+  //  5: jmpf #0, #12     always taken
+  //  8: halt
+  //  9: jmpt #1, #-1     faults, not a computed target
+  // 12: jmpt [30], [30]  computed target
+  // 15: halt
+  const PROGRAM: [i64; 16] = [3, 30, 1005, 30, 9, 1106, 0, 12, 99, 1105, 1, -1, 5, 30, 30, 99];
+
+  fn edges(cfg: &Cfg, start: usize) -> Vec<Edge> {
+    cfg.blocks[&start].edges.clone()
+  }
+
+  #[test]
+  fn leaders_and_edges() {
+    let cfg = Cfg::recover(&PROGRAM);
+    assert_eq!(cfg.blocks.keys().cloned().collect::<Vec<_>>(), vec![0, 5, 8, 9, 12, 15]);
+    assert_eq!(cfg.blocks[&0].instructions.len(), 2);
+    assert_eq!(cfg.blocks[&0].end(), 5);
+    assert_eq!(edges(&cfg, 0), vec![Edge::Jump(9), Edge::FallThrough(5)]);
+    // Constant conditions only keep the way that is taken
+    assert_eq!(edges(&cfg, 5), vec![Edge::Jump(12)]);
+    assert_eq!(edges(&cfg, 9), vec![]);
+    assert_eq!(edges(&cfg, 12), vec![Edge::FallThrough(15)]);
+    assert_eq!(edges(&cfg, 8), vec![]);
+  }
+
+  #[test]
+  fn dynamic_jumps() {
+    let cfg = Cfg::recover(&PROGRAM);
+    assert_eq!(cfg.dynamic_jumps(), vec![12]);
+    assert!(!cfg.blocks[&9].dynamic);
+  }
+
+  #[test]
+  fn dot() {
+    let dot = Cfg::recover(&PROGRAM).to_dot();
+    assert!(dot.starts_with("digraph intcode {\n"));
+    assert!(dot.contains("  b0 [label=\"0: inp [30]\\l2: jmpt [30], #9\\l\"];\n"));
+    assert!(dot.contains("  b0 -> b9;\n"));
+    assert!(dot.contains("  b0 -> b5 [style=dashed];\n"));
+    assert!(dot.contains("  b12 [label=\"12: jmpt [30], [30]\\l-> computed target\\l\", color=red];\n"));
+    assert_eq!(dot.matches("color=red").count(), 1);
+    assert!(dot.ends_with("}\n"));
+  }
+}
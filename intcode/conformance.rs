@@ -0,0 +1,360 @@
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+
+use super::transpile::transpile;
+use super::{Arithmetic, IntcodeError, Interpreter, Isa};
+
+// Test cases for the interpreter, one block of lines per case:
+//
+//   # day02, first example
+//   case day02-example
+//   program 1,9,10,3,2,3,11,0,99,30,40,50
+//   mem 0=3500 3=70
+//
+//   case day05-equal-8
+//   program 3,9,8,9,10,9,4,9,99,-1,8
+//   input 8
+//   output 1
+//
+// `case` starts a case and names it, `program` is required. A case passes
+// when the program halts with exactly the `output` values and the `mem`
+// cells, each `addr=val` or `addr=val,val,...` for consecutive cells, or when
+// it stops with the `error` variant, e.g. `error UnknownOpcode`. Cases run
+// with `fuel` instructions, `DEFAULT_FUEL` unless given, `strict day02`,
+// `strict day05` or `strict day09` runs them with that strict ISA level and
+// `arithmetic checked` makes overflows errors instead of wrapping.
+pub const DEFAULT_FUEL: u64 = 1_000_000;
+
+#[derive(Debug)]
+pub enum CaseError {
+  Io(io::Error),
+  Format { line: usize, msg: String },
+}
+
+impl fmt::Display for CaseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CaseError::Io(e) => write!(f, "{}", e),
+      CaseError::Format { line, msg } => write!(f, "line {}: {}", line, msg),
+    }
+  }
+}
+
+impl error::Error for CaseError {}
+
+impl From<io::Error> for CaseError {
+  fn from(e: io::Error) -> CaseError {
+    CaseError::Io(e)
+  }
+}
+
+fn format_err<T>(line: usize, msg: String) -> Result<T, CaseError> {
+  Err(CaseError::Format { line: line, msg: msg })
+}
+
+fn parse<T: FromStr>(line: usize, s: &str) -> Result<T, CaseError> {
+  match s.trim().parse::<T>() {
+    Ok(val) => Ok(val),
+    Err(_) => format_err(line, format!("invalid number `{}`", s)),
+  }
+}
+
+fn parse_isa(line: usize, s: &str) -> Result<Isa, CaseError> {
+  match s {
+    "day02" => Ok(Isa::Day02),
+    "day05" => Ok(Isa::Day05),
+    "day09" => Ok(Isa::Day09),
+    _ => format_err(line, format!("unknown ISA level `{}`", s)),
+  }
+}
+
+fn parse_arithmetic(line: usize, s: &str) -> Result<Arithmetic, CaseError> {
+  match s {
+    "wrapping" => Ok(Arithmetic::Wrapping),
+    "checked" => Ok(Arithmetic::Checked),
+    _ => format_err(line, format!("unknown arithmetic `{}`", s)),
+  }
+}
+
+fn parse_list(line: usize, s: &str) -> Result<Vec<i64>, CaseError> {
+  if s.trim().is_empty() {
+    return Ok(Vec::new());
+  }
+  s.split(',').map(|val| parse(line, val)).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+  pub name: String,
+  // Where the case starts in its file
+  pub line: usize,
+  pub program: Vec<i64>,
+  pub inputs: Vec<i64>,
+  pub outputs: Option<Vec<i64>>,
+  pub cells: Vec<(usize, i64)>,
+  // Variant name of the expected `IntcodeError`
+  pub error: Option<String>,
+  pub fuel: u64,
+  pub strict: Option<Isa>,
+  pub arithmetic: Arithmetic,
+}
+
+// Variant name of an error, as written in a case's `error` field
+pub fn error_name(e: &IntcodeError) -> String {
+  let debug = format!("{:?}", e);
+  debug.split_whitespace().next().unwrap_or("").to_string()
+}
+
+impl Case {
+  fn new(name: &str, line: usize) -> Case {
+    Case {
+      name: name.to_string(),
+      line: line,
+      program: Vec::new(),
+      inputs: Vec::new(),
+      outputs: None,
+      cells: Vec::new(),
+      error: None,
+      fuel: DEFAULT_FUEL,
+      strict: None,
+      arithmetic: Arithmetic::Wrapping,
+    }
+  }
+
+  // Runs the case on a fresh `Interpreter`, describing the first mismatch
+  pub fn run(&self) -> Result<(), String> {
+    let mut vm = Interpreter::new(self.program.clone());
+    vm.stdin.extend(self.inputs.iter().cloned());
+    vm.fuel = Some(self.fuel);
+    vm.arithmetic = self.arithmetic;
+    if let Some(isa) = self.strict {
+      vm = vm.with_strict_isa(isa);
+    }
+    let error = vm.run_to_halt().err().map(|e| (error_name(&e), e.to_string()));
+    let outputs = vm.stdout.iter().cloned().collect::<Vec<_>>();
+    let cells = self.cells.iter().map(|&(addr, _)| vm.mem[addr]).collect::<Vec<_>>();
+    self.check(error, &outputs, &cells)
+  }
+
+  // Whether `transpile` can run the case, its runtime has no fuel and no
+  // strict ISA levels
+  pub fn transpilable(&self) -> bool {
+    self.strict.is_none() && self.error.as_ref().is_none_or(|name| name != "OutOfFuel")
+  }
+
+  // Compares how a run ended with the expectations, `error` holds the variant
+  // name and the message, `cells` the values at the addresses of `self.cells`
+  fn check(&self, error: Option<(String, String)>, outputs: &[i64], cells: &[i64]) -> Result<(), String> {
+    match (&self.error, error) {
+      (None, Some((_, msg))) => return Err(format!("unexpected error: {}", msg)),
+      (Some(name), None) => return Err(format!("halted, expected {}", name)),
+      (Some(name), Some((actual, msg))) if actual != *name => return Err(format!("expected {}, got {}", name, msg)),
+      _ => (),
+    }
+    if let Some(expected) = &self.outputs {
+      if outputs != &expected[..] {
+        return Err(format!("expected outputs {:?}, got {:?}", expected, outputs));
+      }
+    }
+    for (&(addr, val), &actual) in self.cells.iter().zip(cells) {
+      if actual != val {
+        return Err(format!("expected {} at address {}, got {}", val, addr, actual));
+      }
+    }
+    Ok(())
+  }
+}
+
+// Source of a program running every case through the `Machine` `transpile`
+// generates for it, printing one line per case: how it ended (`halted` or
+// `error <variant> <message>`), the outputs and the checked cells, separated
+// by tabs
+fn harness(cases: &[&Case]) -> String {
+  let mut out = String::new();
+  for (i, case) in cases.iter().enumerate() {
+    out.push_str(&format!("mod case_{} {{\n{}}}\n\n", i, transpile(&case.program, case.arithmetic, false)));
+  }
+  out.push_str("fn join(vals: &[i64]) -> String {\n");
+  out.push_str("  vals.iter().map(|val| val.to_string()).collect::<Vec<_>>().join(\",\")\n");
+  out.push_str("}\n\n");
+  out.push_str("fn main() {\n");
+  for (i, case) in cases.iter().enumerate() {
+    let addrs = case.cells.iter().map(|&(addr, _)| addr.to_string()).collect::<Vec<_>>();
+    out.push_str(&format!("  let mut machine = case_{}::Machine::new();\n", i));
+    out.push_str(&format!("  machine.stdin.extend(Vec::<i64>::from([{}]));\n", join(&case.inputs)));
+    out.push_str("  let end = match machine.execute() {\n");
+    out.push_str(&format!("    Ok(case_{}::State::Halted) => \"halted\".to_string(),\n", i));
+    out.push_str("    Ok(_) => \"error InputExhausted input exhausted\".to_string(),\n");
+    out.push_str("    Err(e) => format!(\"error {} {}\", e.kind, e),\n");
+    out.push_str("  };\n");
+    out.push_str("  let outputs = machine.stdout.iter().cloned().collect::<Vec<_>>();\n");
+    out.push_str(&format!("  let cells = [{}].iter().map(|&addr: &usize| machine.read(addr)).collect::<Vec<_>>();\n", addrs.join(",")));
+    out.push_str("  println!(\"{}\\t{}\\t{}\", end, join(&outputs), join(&cells));\n");
+  }
+  out.push_str("}\n");
+  out
+}
+
+fn join(vals: &[i64]) -> String {
+  vals.iter().map(|val| val.to_string()).collect::<Vec<_>>().join(",")
+}
+
+// Runs `cases` through transpiled code instead of the `Interpreter`, in a
+// single program built in `dir` with `rustc` (or `$RUSTC`). Cases it can't
+// express, see `Case::transpilable`, come back as None.
+pub fn run_transpiled(cases: &[Case], dir: &Path) -> io::Result<Vec<Option<Result<(), String>>>> {
+  let runnable = cases.iter().filter(|case| case.transpilable()).collect::<Vec<_>>();
+  fs::create_dir_all(dir)?;
+  let source = dir.join("harness.rs");
+  let binary = dir.join("harness");
+  fs::write(&source, harness(&runnable))?;
+
+  let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+  let build = Command::new(rustc).arg("--edition=2018").arg("-o").arg(&binary).arg(&source).output()?;
+  if !build.status.success() {
+    let msg = format!("cannot build {}: {}", source.display(), String::from_utf8_lossy(&build.stderr));
+    return Err(io::Error::other(msg));
+  }
+  let run = Command::new(&binary).output()?;
+  let stdout = String::from_utf8_lossy(&run.stdout);
+  let mut lines = stdout.lines();
+
+  let mut results = Vec::new();
+  for case in cases {
+    if !case.transpilable() {
+      results.push(None);
+      continue;
+    }
+    let result = match lines.next() {
+      Some(line) => {
+        let fields = line.split('\t').collect::<Vec<_>>();
+        let error = fields[0].strip_prefix("error ").map(|rest| match rest.find(' ') {
+          Some(i) => (rest[..i].to_string(), rest[i + 1..].to_string()),
+          None => (rest.to_string(), String::new()),
+        });
+        let parse = |field: &str| field.split(',').filter(|val| !val.is_empty())
+          .map(|val| val.parse::<i64>().unwrap_or(0)).collect::<Vec<_>>();
+        case.check(error, &parse(fields.get(1).unwrap_or(&"")), &parse(fields.get(2).unwrap_or(&"")))
+      },
+      None => Err(format!("harness stopped early: {}", String::from_utf8_lossy(&run.stderr).trim())),
+    };
+    results.push(Some(result));
+  }
+  Ok(results)
+}
+
+pub fn parse_cases(text: &str) -> Result<Vec<Case>, CaseError> {
+  let mut cases: Vec<Case> = Vec::new();
+  for (i, text) in text.lines().enumerate() {
+    let line = i + 1;
+    let text = text.trim();
+    if text.is_empty() || text.starts_with('#') {
+      continue;
+    }
+    let (key, rest) = match text.find(' ') {
+      Some(i) => (&text[..i], text[i + 1..].trim()),
+      None => (text, ""),
+    };
+    if key == "case" {
+      if rest.is_empty() {
+        return format_err(line, "case without a name".to_string());
+      }
+      cases.push(Case::new(rest, line));
+      continue;
+    }
+    let case = match cases.last_mut() {
+      Some(case) => case,
+      None => return format_err(line, format!("`{}` before the first case", key)),
+    };
+    match key {
+      "program" => case.program = parse_list(line, rest)?,
+      "input" => case.inputs.extend(parse_list(line, rest)?),
+      "output" => case.outputs = Some(parse_list(line, rest)?),
+      "mem" => {
+        for cell in rest.split_whitespace() {
+          let (addr, vals) = match cell.find('=') {
+            Some(i) => (parse::<usize>(line, &cell[..i])?, parse_list(line, &cell[i + 1..])?),
+            None => return format_err(line, format!("expected `addr=val`, got `{}`", cell)),
+          };
+          case.cells.extend(vals.into_iter().enumerate().map(|(i, val)| (addr + i, val)));
+        }
+      },
+      "error" => case.error = Some(rest.to_string()),
+      "fuel" => case.fuel = parse(line, rest)?,
+      "strict" => case.strict = Some(parse_isa(line, rest)?),
+      "arithmetic" => case.arithmetic = parse_arithmetic(line, rest)?,
+      _ => return format_err(line, format!("unknown field `{}`", key)),
+    }
+  }
+  for case in &cases {
+    if case.program.is_empty() {
+      return format_err(case.line, format!("case `{}` has no program", case.name));
+    }
+  }
+  Ok(cases)
+}
+
+pub fn load_cases<P: AsRef<Path>>(path: P) -> Result<Vec<Case>, CaseError> {
+  parse_cases(&fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const FILES: [(&str, &str); 5] = [
+    ("arithmetic", include_str!("../conformance/arithmetic.cases")),
+    ("day02", include_str!("../conformance/day02.cases")),
+    ("day05", include_str!("../conformance/day05.cases")),
+    ("day09", include_str!("../conformance/day09.cases")),
+    ("errors", include_str!("../conformance/errors.cases")),
+  ];
+
+  fn cases(name: &str, text: &str) -> Vec<Case> {
+    parse_cases(text).unwrap_or_else(|e| panic!("invalid {}.cases: {}", name, e))
+  }
+
+  #[test]
+  fn interpreter() {
+    let mut failures = Vec::new();
+    for (name, text) in FILES.iter() {
+      for case in cases(name, text) {
+        if let Err(msg) = case.run() {
+          failures.push(format!("{} ({}:{}): {}", case.name, name, case.line, msg));
+        }
+      }
+    }
+    assert!(failures.is_empty(), "failed cases:\n{}", failures.join("\n"));
+  }
+
+  // Builds the generated code with rustc
+  #[test]
+  fn transpiled() {
+    let mut failures = Vec::new();
+    for (name, text) in FILES.iter() {
+      let cases = cases(name, text);
+      let dir = env::temp_dir().join(format!("intcode-conformance-{}-{}", std::process::id(), name));
+      let results = run_transpiled(&cases, &dir).unwrap_or_else(|e| panic!("{}.cases: {}", name, e));
+      for (case, result) in cases.iter().zip(results) {
+        if let Some(Err(msg)) = result {
+          failures.push(format!("{} ({}:{}): {}", case.name, name, case.line, msg));
+        }
+      }
+    }
+    assert!(failures.is_empty(), "failed cases:\n{}", failures.join("\n"));
+  }
+
+  #[test]
+  fn format_errors() {
+    assert!(parse_cases("program 99\n").is_err());
+    assert!(parse_cases("case empty\n").is_err());
+    assert!(parse_cases("case x\nprogram 99\narithmetic saturating\n").is_err());
+    assert!(parse_cases("case x\nprogram 99\nstrict day07\n").is_err());
+  }
+}
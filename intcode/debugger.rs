@@ -0,0 +1,458 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use super::{Interpreter, State};
+use super::disasm;
+
+const HELP: &str = "\
+break <addr>          set a breakpoint (alias b)
+delete <addr>         remove a breakpoint (alias d)
+breaks                list breakpoints
+step [n]              execute n instructions, default 1 (alias s)
+continue              run until a breakpoint, halt or interrupt (alias c)
+back [n]              undo n steps, default 1 (alias rs)
+rcontinue             undo steps until a breakpoint (alias rc)
+who <addr>            show the last step that wrote addr
+regs                  show iptr, rptr, state and current instruction (alias r)
+list [addr] [n]       disassemble n instructions from addr (alias l)
+mem <addr> [n]        dump n memory cells (alias x)
+set <addr> <value>    write a memory cell
+iptr <value>          move the instruction pointer
+rptr <value>          move the relative base
+input <value>...      queue values on stdin
+stdin                 show pending inputs
+stdout                show pending outputs
+save <path>           write a snapshot of the machine
+quit                  stop the program (alias q)
+";
+
+// Most cells `mem`, or instructions `list`, shows at once
+const MAX_DUMP: usize = 1 << 16;
+
+// What the command loop decided about the machine
+enum Resume {
+  Steps(usize),
+  Continue,
+  Quit,
+}
+
+// Command loop around an `Interpreter`. Commands are read from `input`, which
+// is either a terminal or a script; everything is reported to `output`.
+//
+// A host that drives the machine itself calls `hook` before every `step`,
+// `run` drives the machine from the debugger alone.
+pub struct Debugger<R = Box<dyn BufRead>, W = Box<dyn Write>> {
+  input: R,
+  output: W,
+  // Echo commands so that transcripts of scripted sessions are readable
+  echo: bool,
+
+  breakpoints: BTreeSet<usize>,
+  // Remaining steps before the next prompt, None when continuing
+  countdown: Option<usize>,
+  prev_iptr: Option<usize>,
+}
+
+impl Debugger {
+  pub fn interactive() -> Debugger {
+    Debugger::new(Box::new(BufReader::new(io::stdin())), Box::new(io::stdout()))
+  }
+
+  pub fn from_script<P: AsRef<Path>>(path: P) -> io::Result<Debugger> {
+    let file = File::open(path)?;
+    let mut debugger: Debugger = Debugger::new(Box::new(BufReader::new(file)), Box::new(io::stdout()));
+    debugger.echo = true;
+    Ok(debugger)
+  }
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+  pub fn new(input: R, output: W) -> Debugger<R, W> {
+    Debugger {
+      input: input,
+      output: output,
+      echo: false,
+      breakpoints: BTreeSet::new(),
+      // Stop before the very first instruction
+      countdown: Some(0),
+      prev_iptr: None,
+    }
+  }
+
+  pub fn set_echo(&mut self, echo: bool) {
+    self.echo = echo;
+  }
+
+  pub fn add_breakpoint(&mut self, addr: usize) {
+    self.breakpoints.insert(addr);
+  }
+
+  pub fn remove_breakpoint(&mut self, addr: usize) {
+    self.breakpoints.remove(&addr);
+  }
+
+  pub fn output(&self) -> &W {
+    &self.output
+  }
+
+  pub fn into_output(self) -> W {
+    self.output
+  }
+
+  // Call before every `step` of `vm`. Returns false once the user quits.
+  pub fn hook(&mut self, vm: &mut Interpreter) -> io::Result<bool> {
+    let iptr = vm.iptr;
+    // A machine still waiting for input where the last hook saw it hasn't
+    // executed that instruction yet, its breakpoint already fired
+    let repeat = vm.state == State::AwaitingInput && self.prev_iptr == Some(iptr);
+    let at_break = self.breakpoints.contains(&iptr) && !repeat;
+    self.prev_iptr = Some(iptr);
+
+    let pause = match self.countdown {
+      Some(0) => true,
+      Some(n) => {
+        self.countdown = Some(n - 1);
+        at_break
+      },
+      None => at_break,
+    };
+    if !pause {
+      return Ok(true);
+    }
+    if at_break {
+      writeln!(self.output, "breakpoint at {}", iptr)?;
+    }
+    self.prompt(vm)
+  }
+
+  // Runs `vm` under the debugger until it halts and the user quits, or the
+  // command source runs out
+  pub fn run(&mut self, vm: &mut Interpreter) -> io::Result<()> {
+    loop {
+      if !self.hook(vm)? {
+        return Ok(());
+      }
+      match vm.step() {
+        Ok(state) if state.can_continue() => continue,
+        Ok(state) => writeln!(self.output, "{}", describe_state(state))?,
+        Err(e) => writeln!(self.output, "error: {}", e)?,
+      }
+      // Halted, interrupted or faulted, hand control back to the user
+      self.countdown = Some(0);
+    }
+  }
+
+  fn prompt(&mut self, vm: &mut Interpreter) -> io::Result<bool> {
+    self.show_current(vm)?;
+    loop {
+      write!(self.output, "(icdb) ")?;
+      self.output.flush()?;
+      let mut line = String::new();
+      if self.input.read_line(&mut line)? == 0 {
+        // End of input behaves like quit
+        writeln!(self.output)?;
+        return Ok(false);
+      }
+      let line = line.trim();
+      if self.echo {
+        writeln!(self.output, "{}", line)?;
+      }
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+
+      match self.command(vm, line)? {
+        Some(Resume::Quit) => return Ok(false),
+        Some(Resume::Steps(n)) => {
+          if vm.state == State::Halted {
+            writeln!(self.output, "program halted")?;
+            continue;
+          }
+          self.countdown = Some(n - 1);
+          return Ok(true);
+        },
+        Some(Resume::Continue) => {
+          if vm.state == State::Halted {
+            writeln!(self.output, "program halted")?;
+            continue;
+          }
+          self.countdown = None;
+          return Ok(true);
+        },
+        None => (),
+      }
+    }
+  }
+
+  fn command(&mut self, vm: &mut Interpreter, line: &str) -> io::Result<Option<Resume>> {
+    let mut words = line.split_whitespace();
+    let cmd = words.next().unwrap_or("");
+    let args: Vec<&str> = words.collect();
+    if cmd == "save" && args.len() == 1 {
+      match vm.save_to_file(args[0]) {
+        Ok(()) => writeln!(self.output, "saved snapshot to {}", args[0])?,
+        Err(e) => writeln!(self.output, "cannot save snapshot: {}", e)?,
+      }
+      return Ok(None);
+    }
+    let nums: Vec<i64> = match args.iter().map(|a| a.parse::<i64>()).collect() {
+      Ok(nums) => nums,
+      Err(_) => {
+        writeln!(self.output, "invalid number in `{}`", line)?;
+        return Ok(None);
+      },
+    };
+    let addr_arg = |i: usize| nums.get(i).filter(|n| **n >= 0).map(|n| *n as usize);
+
+    match (cmd, nums.len()) {
+      ("break", 1) | ("b", 1) => match addr_arg(0) {
+        Some(addr) => {
+          self.breakpoints.insert(addr);
+          writeln!(self.output, "breakpoint at {}", addr)?;
+        },
+        None => writeln!(self.output, "invalid address")?,
+      },
+      ("delete", 1) | ("d", 1) => match addr_arg(0) {
+        Some(addr) if self.breakpoints.remove(&addr) => writeln!(self.output, "deleted breakpoint at {}", addr)?,
+        _ => writeln!(self.output, "no breakpoint there")?,
+      },
+      ("breaks", 0) => {
+        let list: Vec<String> = self.breakpoints.iter().map(|addr| addr.to_string()).collect();
+        writeln!(self.output, "breakpoints: {}", list.join(", "))?;
+      },
+      ("step", 0) | ("s", 0) => return Ok(Some(Resume::Steps(1))),
+      ("step", 1) | ("s", 1) => match addr_arg(0) {
+        Some(n) if n > 0 => return Ok(Some(Resume::Steps(n))),
+        _ => writeln!(self.output, "invalid step count")?,
+      },
+      ("continue", 0) | ("c", 0) => return Ok(Some(Resume::Continue)),
+      ("quit", 0) | ("q", 0) => return Ok(Some(Resume::Quit)),
+      ("back", _) | ("rs", _) | ("rcontinue", 0) | ("rc", 0) | ("who", 1) if vm.journal.is_none() =>
+        writeln!(self.output, "journal is off")?,
+      ("back", 0) | ("rs", 0) | ("back", 1) | ("rs", 1) => {
+        let count = addr_arg(0).unwrap_or(1);
+        let mut undone = 0;
+        while undone < count && vm.step_back() {
+          undone += 1;
+        }
+        if undone < count {
+          writeln!(self.output, "start of journal reached after {} steps", undone)?;
+        }
+        self.show_current(vm)?;
+      },
+      ("rcontinue", 0) | ("rc", 0) => {
+        loop {
+          if !vm.step_back() {
+            writeln!(self.output, "start of journal reached")?;
+            break;
+          }
+          if self.breakpoints.contains(&vm.iptr) {
+            writeln!(self.output, "breakpoint at {}", vm.iptr)?;
+            break;
+          }
+        }
+        self.show_current(vm)?;
+      },
+      ("who", 1) => match addr_arg(0) {
+        Some(addr) => match vm.journal.as_ref().and_then(|journal| journal.last_write(addr)) {
+          Some(entry) => {
+            // The first old and the last new value, should the step have written it twice
+            let mut writes = entry.writes.iter().filter(|&&(a, _, _)| a == addr);
+            let (_, old, mut new) = *writes.next().unwrap();
+            if let Some(&(_, _, last)) = writes.next_back() {
+              new = last;
+            }
+            let (text, _) = describe_at(vm, entry.iptr);
+            writeln!(self.output, "step {} iptr {} wrote {} -> {}  {}", entry.step, entry.iptr, old, new, text)?;
+          },
+          None => writeln!(self.output, "no write to {} in the journal", addr)?,
+        },
+        None => writeln!(self.output, "invalid address")?,
+      },
+      ("regs", 0) | ("r", 0) => self.show_current(vm)?,
+      ("list", _) | ("l", _) if nums.len() <= 2 => {
+        let mut addr = addr_arg(0).unwrap_or(vm.iptr);
+        let count = addr_arg(1).unwrap_or(10);
+        if count > MAX_DUMP {
+          writeln!(self.output, "at most {} instructions at a time", MAX_DUMP)?;
+        } else {
+          for _ in 0..count {
+            let marker = if addr == vm.iptr { ">" } else { " " };
+            let (text, size) = describe_at(vm, addr);
+            writeln!(self.output, "{}{:>6}: {}", marker, addr, text)?;
+            addr = match addr.checked_add(size) {
+              Some(next) => next,
+              None => break,
+            };
+          }
+        }
+      },
+      ("mem", 1) | ("x", 1) | ("mem", 2) | ("x", 2) => match addr_arg(0) {
+        Some(addr) => match addr.checked_add(addr_arg(1).unwrap_or(1)) {
+          Some(end) if end - addr > MAX_DUMP => writeln!(self.output, "at most {} cells at a time", MAX_DUMP)?,
+          Some(end) => {
+            let cells: Vec<String> = (addr..end).map(|a| vm.mem.get(a).to_string()).collect();
+            writeln!(self.output, "{}: {}", addr, cells.join(","))?;
+          },
+          None => writeln!(self.output, "invalid range")?,
+        },
+        None => writeln!(self.output, "invalid address")?,
+      },
+      ("set", 2) => match addr_arg(0) {
+        Some(addr) => {
+          vm.mem.set(addr, nums[1]);
+          writeln!(self.output, "{} = {}", addr, nums[1])?;
+        },
+        None => writeln!(self.output, "invalid address")?,
+      },
+      ("iptr", 1) => match addr_arg(0) {
+        Some(addr) => {
+          vm.iptr = addr;
+          self.show_current(vm)?;
+        },
+        None => writeln!(self.output, "invalid address")?,
+      },
+      ("rptr", 1) => match addr_arg(0) {
+        Some(addr) => {
+          vm.rptr = addr;
+          writeln!(self.output, "rptr = {}", addr)?;
+        },
+        None => writeln!(self.output, "invalid address")?,
+      },
+      ("input", n) if n > 0 => {
+        vm.stdin.extend(nums.iter());
+        writeln!(self.output, "stdin: {:?}", vm.stdin)?;
+      },
+      ("stdin", 0) => writeln!(self.output, "stdin: {:?}", vm.stdin)?,
+      ("stdout", 0) => writeln!(self.output, "stdout: {:?}", vm.stdout)?,
+      ("help", _) | ("h", _) => write!(self.output, "{}", HELP)?,
+      _ => writeln!(self.output, "unknown command `{}`, try `help`", line)?,
+    }
+    Ok(None)
+  }
+
+  fn show_current(&mut self, vm: &Interpreter) -> io::Result<()> {
+    let (text, _) = describe_at(vm, vm.iptr);
+    writeln!(self.output, "iptr={} rptr={} state={:?}  {}", vm.iptr, vm.rptr, vm.state, text)
+  }
+}
+
+// Disassembly of the cell(s) at `addr` and how many cells that covers
+fn describe_at(vm: &Interpreter, addr: usize) -> (String, usize) {
+  match disasm::decode_mem(&vm.mem, &vm.opcodes, addr) {
+    Some(ins) => (ins.to_string(), ins.size()),
+    None => (format!("DATA {}", vm.mem.get(addr)), 1),
+  }
+}
+
+fn describe_state(state: State) -> &'static str {
+  match state {
+    State::Halted => "program halted",
+    State::AwaitingInput => "waiting for input",
+    State::OutOfFuel => "out of fuel",
+    State::Idle | State::Running | State::OutputReady(_) => "running",
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::env;
+  use std::fs;
+  use std::io::Cursor;
+  use std::process;
+
+  // Runs `script` against `vm`, returns the transcript
+  fn session(vm: &mut Interpreter, script: &str) -> String {
+    let mut debugger = Debugger::new(Cursor::new(script.to_string()), Vec::new());
+    debugger.set_echo(true);
+    debugger.run(vm).unwrap();
+    String::from_utf8(debugger.into_output()).unwrap()
+  }
+
+  // Counts 3, 2, 1 down on stdout
+  fn countdown() -> Interpreter {
+    Interpreter::new(vec![1101, 0, 3, 20, 4, 20, 1001, 20, -1, 20, 1005, 20, 4, 99])
+  }
+
+  #[test]
+  fn step_and_continue() {
+    let mut vm = countdown();
+    let out = session(&mut vm, "step\nstep 2\nregs\ncontinue\n");
+    assert!(out.contains("iptr=4 rptr=0 state=Idle  out [20]"));
+    assert!(out.contains("iptr=10 rptr=0 state=Idle  jmpt [20], #4"));
+    assert!(out.contains("program halted"));
+    assert_eq!(vm.state, State::Halted);
+    assert_eq!(vm.stdout, vec![3, 2, 1]);
+  }
+
+  #[test]
+  fn breakpoints() {
+    let mut vm = countdown();
+    let out = session(&mut vm, "break 4\nc\nstdout\nc\nstdout\ndelete 4\nc\n");
+    assert_eq!(out.lines().filter(|line| *line == "breakpoint at 4").count(), 3);
+    assert!(out.contains("stdout: []"));
+    assert!(out.contains("stdout: [3]"));
+    assert_eq!(vm.state, State::Halted);
+    assert_eq!(vm.stdout, vec![3, 2, 1]);
+  }
+
+  #[test]
+  fn breakpoint_on_jump_to_itself() {
+    let mut vm = Interpreter::new(vec![1105, 1, 0]);
+    vm.fuel = Some(100);
+    let out = session(&mut vm, "b 0\nc\nc\nc\nq\n");
+    // Once when set, then once per continue
+    assert_eq!(out.lines().filter(|line| *line == "breakpoint at 0").count(), 4);
+    assert!(!out.contains("out of fuel"));
+  }
+
+  #[test]
+  fn breakpoint_on_input() {
+    let mut vm = Interpreter::new(vec![1101, 0, 0, 20, 3, 20, 4, 20, 99]);
+    let out = session(&mut vm, "b 4\nc\nc\nc\ninput 5\nc\n");
+    assert_eq!(out.lines().filter(|line| *line == "breakpoint at 4").count(), 2);
+    assert_eq!(out.matches("waiting for input").count(), 2);
+    assert!(out.contains("waiting for input"));
+    assert_eq!(vm.stdout, vec![5]);
+  }
+
+  #[test]
+  fn memory() {
+    let mut vm = countdown();
+    let out = session(&mut vm, "mem 0 4\nset 21 7\nx 20 2\nmem 9223372036854775807 9223372036854775807\nmem 0 70000\nq\n");
+    assert!(out.contains("0: 1101,0,3,20\n"));
+    assert!(out.contains("21 = 7\n"));
+    assert!(out.contains("20: 0,7\n"));
+    assert!(out.contains("at most 65536 cells at a time"));
+    assert_eq!(vm.mem[21], 7);
+  }
+
+  #[test]
+  fn list() {
+    let mut vm = countdown();
+    let out = session(&mut vm, "list 0 2
+list 0 9223372036854775807
+l 9223372036854775807 3
+q
+");
+    assert!(out.contains(">     0: "));
+    assert!(out.contains("at most 65536 instructions at a time"));
+    assert!(out.contains(" 9223372036854775807: "));
+  }
+
+  #[test]
+  fn save() {
+    let path = env::temp_dir().join(format!("icdb-test-{}.snapshot", process::id()));
+    let mut vm = countdown();
+    let out = session(&mut vm, &format!("s 2\nsave {}\nq\n", path.display()));
+    assert!(out.contains("saved snapshot"));
+    let loaded = Interpreter::load_from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.iptr, 6);
+    assert_eq!(loaded.stdout, vec![3]);
+    assert_eq!(loaded.mem.to_vec(), vm.mem.to_vec());
+  }
+}
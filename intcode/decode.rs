@@ -0,0 +1,95 @@
+use std::cmp;
+
+use super::*;
+
+// Everything `step` needs to know about the cell at `iptr`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decoded {
+  pub op: i64,
+  // Mode digit of every parameter, not checked yet
+  pub modes: [i64; 3],
+  // Cells the instruction covers, 1 for unknown opcodes
+  pub size: usize,
+}
+
+impl Decoded {
+  pub fn new(raw: i64) -> Decoded {
+    let op = raw % 100;
+    let modes = (raw - op) / 100;
+    Decoded {
+      op: op,
+      modes: [digit_at(modes, 0), digit_at(modes, 1), digit_at(modes, 2)],
+      size: match op {
+        ADD_INS => ADD_SIZE,
+        MULT_INS => MULT_SIZE,
+        INP_INS => INP_SIZE,
+        OUT_INS => OUT_SIZE,
+        JMPT_INS => JMPT_SIZE,
+        JMPF_INS => JMPF_SIZE,
+        TLS_INS => TLS_SIZE,
+        TEQ_INS => TEQ_SIZE,
+        SRL_INS => SRL_SIZE,
+        HALT_INS => HALT_SIZE,
+        _ => 1,
+      },
+    }
+  }
+
+  // A cell too large to hold an instruction, nothing has opcode -1
+  pub fn unknown() -> Decoded {
+    Decoded { op: -1, modes: [0; 3], size: 1 }
+  }
+}
+
+// Instructions at higher addresses are decoded every time they run, so a
+// jump far out doesn't grow the cache with it
+pub const CACHED_ADDRS: usize = 1 << 16;
+
+// Largest instruction size, how far back a write may reach into one
+const MAX_SIZE: usize = 4;
+
+// Decoded instructions by address
+#[derive(Debug, Clone, Default)]
+pub struct DecodeCache {
+  entries: Vec<Option<Decoded>>,
+}
+
+impl DecodeCache {
+  pub fn new() -> DecodeCache {
+    DecodeCache::default()
+  }
+
+  pub fn get(&self, addr: usize) -> Option<Decoded> {
+    match self.entries.get(addr) {
+      Some(&entry) => entry,
+      None => None,
+    }
+  }
+
+  pub fn insert(&mut self, addr: usize, ins: Decoded) {
+    if addr >= CACHED_ADDRS {
+      return;
+    }
+    if addr >= self.entries.len() {
+      self.entries.resize(addr + 1, None);
+    }
+    self.entries[addr] = Some(ins);
+  }
+
+  // Drops every cached instruction that covers `addr`
+  pub fn invalidate(&mut self, addr: usize) {
+    let start = addr.saturating_sub(MAX_SIZE - 1);
+    let end = cmp::min(addr + 1, self.entries.len());
+    for at in start..end {
+      if let Some(ins) = self.entries[at] {
+        if at + ins.size > addr {
+          self.entries[at] = None;
+        }
+      }
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.entries.clear();
+  }
+}
@@ -0,0 +1,166 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{Receiver, Sender};
+
+// Where `INP_INS` takes its values from once the `stdin` queue is empty.
+// Returning None means nothing is available yet and the machine interrupts.
+// `C` is the machine's cell type.
+pub trait InputSource<C = i64> {
+  fn read(&mut self) -> Option<C>;
+}
+
+// Where `OUT_INS` sends its values instead of the `stdout` queue
+pub trait OutputSink<C = i64> {
+  fn write(&mut self, val: C);
+}
+
+impl<C> fmt::Debug for dyn InputSource<C> + Send {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "InputSource")
+  }
+}
+
+impl<C> fmt::Debug for dyn OutputSink<C> + Send {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "OutputSink")
+  }
+}
+
+impl<C> InputSource<C> for VecDeque<C> {
+  fn read(&mut self) -> Option<C> {
+    self.pop_front()
+  }
+}
+
+impl<C> OutputSink<C> for VecDeque<C> {
+  fn write(&mut self, val: C) {
+    self.push_back(val);
+  }
+}
+
+impl<C> OutputSink<C> for Vec<C> {
+  fn write(&mut self, val: C) {
+    self.push(val);
+  }
+}
+
+impl<C, F: FnMut() -> Option<C>> InputSource<C> for F {
+  fn read(&mut self) -> Option<C> {
+    self()
+  }
+}
+
+impl<C, F: FnMut(C)> OutputSink<C> for F {
+  fn write(&mut self, val: C) {
+    self(val)
+  }
+}
+
+// Blocks until a value arrives, a closed channel counts as no input
+impl<C> InputSource<C> for Receiver<C> {
+  fn read(&mut self) -> Option<C> {
+    self.recv().ok()
+  }
+}
+
+// Values sent after the receiver is gone are dropped
+impl<C> OutputSink<C> for Sender<C> {
+  fn write(&mut self, val: C) {
+    let _ = self.send(val);
+  }
+}
+
+// Integers separated by commas or whitespace, e.g. a file of inputs
+pub struct ReaderInput<R> {
+  reader: R,
+  pending: VecDeque<i64>,
+}
+
+impl ReaderInput<BufReader<File>> {
+  pub fn open<P: AsRef<Path>>(path: P) -> io::Result<ReaderInput<BufReader<File>>> {
+    Ok(ReaderInput::new(BufReader::new(File::open(path)?)))
+  }
+}
+
+impl<R: BufRead> ReaderInput<R> {
+  pub fn new(reader: R) -> ReaderInput<R> {
+    ReaderInput {
+      reader: reader,
+      pending: VecDeque::new(),
+    }
+  }
+}
+
+impl<R: BufRead> InputSource for ReaderInput<R> {
+  fn read(&mut self) -> Option<i64> {
+    while self.pending.is_empty() {
+      let mut line = String::new();
+      match self.reader.read_line(&mut line) {
+        Ok(0) | Err(_) => return None,
+        Ok(_) => (),
+      }
+      // Anything that isn't a number ends the input
+      for word in line.split(|c: char| c == ',' || c.is_whitespace()).filter(|w| !w.is_empty()) {
+        match word.parse::<i64>() {
+          Ok(val) => self.pending.push_back(val),
+          Err(_) => return None,
+        }
+      }
+    }
+    self.pending.pop_front()
+  }
+}
+
+// One value per line, e.g. a file of outputs
+pub struct WriterOutput<W: Write> {
+  writer: W,
+}
+
+impl WriterOutput<BufWriter<File>> {
+  pub fn create<P: AsRef<Path>>(path: P) -> io::Result<WriterOutput<BufWriter<File>>> {
+    Ok(WriterOutput::new(BufWriter::new(File::create(path)?)))
+  }
+}
+
+impl<W: Write> WriterOutput<W> {
+  pub fn new(writer: W) -> WriterOutput<W> {
+    WriterOutput { writer: writer }
+  }
+}
+
+impl<W: Write> OutputSink for WriterOutput<W> {
+  fn write(&mut self, val: i64) {
+    let _ = writeln!(self.writer, "{}", val);
+    let _ = self.writer.flush();
+  }
+}
+
+// Prompts on the terminal for every input and prints every output
+pub struct Terminal;
+
+impl InputSource for Terminal {
+  fn read(&mut self) -> Option<i64> {
+    let stdin = io::stdin();
+    loop {
+      eprint!("input> ");
+      let mut line = String::new();
+      match stdin.lock().read_line(&mut line) {
+        Ok(0) | Err(_) => return None,
+        Ok(_) => (),
+      }
+      match line.trim().parse::<i64>() {
+        Ok(val) => return Some(val),
+        Err(_) => eprintln!("not a number: {}", line.trim()),
+      }
+    }
+  }
+}
+
+impl OutputSink for Terminal {
+  fn write(&mut self, val: i64) {
+    println!("{}", val);
+  }
+}
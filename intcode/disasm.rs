@@ -0,0 +1,301 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+  Read,
+  Write,
+}
+
+// Borrowed for the builtin instructions, owned for registered ones
+#[derive(Debug, Clone)]
+pub struct OpInfo {
+  pub code: i64,
+  pub name: Cow<'static, str>,
+  pub params: Cow<'static, [Role]>,
+}
+
+impl OpInfo {
+  pub fn size(&self) -> usize {
+    self.params.len() + 1
+  }
+}
+
+impl PartialEq for OpInfo {
+  fn eq(&self, other: &OpInfo) -> bool {
+    self.code == other.code
+  }
+}
+
+impl Eq for OpInfo {}
+
+pub static OPCODES: [OpInfo; 10] = [
+  OpInfo { code: ADD_INS, name: Cow::Borrowed("add"), params: Cow::Borrowed(&[Role::Read, Role::Read, Role::Write]) },
+  OpInfo { code: MULT_INS, name: Cow::Borrowed("mult"), params: Cow::Borrowed(&[Role::Read, Role::Read, Role::Write]) },
+  OpInfo { code: INP_INS, name: Cow::Borrowed("inp"), params: Cow::Borrowed(&[Role::Write]) },
+  OpInfo { code: OUT_INS, name: Cow::Borrowed("out"), params: Cow::Borrowed(&[Role::Read]) },
+  OpInfo { code: JMPT_INS, name: Cow::Borrowed("jmpt"), params: Cow::Borrowed(&[Role::Read, Role::Read]) },
+  OpInfo { code: JMPF_INS, name: Cow::Borrowed("jmpf"), params: Cow::Borrowed(&[Role::Read, Role::Read]) },
+  OpInfo { code: TLS_INS, name: Cow::Borrowed("tls"), params: Cow::Borrowed(&[Role::Read, Role::Read, Role::Write]) },
+  OpInfo { code: TEQ_INS, name: Cow::Borrowed("teq"), params: Cow::Borrowed(&[Role::Read, Role::Read, Role::Write]) },
+  OpInfo { code: SRL_INS, name: Cow::Borrowed("srl"), params: Cow::Borrowed(&[Role::Read]) },
+  OpInfo { code: HALT_INS, name: Cow::Borrowed("halt"), params: Cow::Borrowed(&[]) },
+];
+
+pub fn opcode_info(code: i64) -> Option<&'static OpInfo> {
+  OPCODES.iter().find(|info| info.code == code)
+}
+
+pub fn opcode_by_name(name: &str) -> Option<&'static OpInfo> {
+  OPCODES.iter().find(|info| info.name == name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+  Position(i64),
+  Immediate(i64),
+  Relative(i64),
+}
+
+impl Operand {
+  pub fn mode(&self) -> i64 {
+    match self {
+      Operand::Position(_) => POSITION,
+      Operand::Immediate(_) => IMMEDIATE,
+      Operand::Relative(_) => RELATIVE,
+    }
+  }
+
+  pub fn value(&self) -> i64 {
+    match *self {
+      Operand::Position(v) | Operand::Immediate(v) | Operand::Relative(v) => v,
+    }
+  }
+}
+
+impl fmt::Display for Operand {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Operand::Position(addr) => write!(f, "[{}]", addr),
+      Operand::Immediate(val) => write!(f, "#{}", val),
+      Operand::Relative(ofst) if ofst < 0 => write!(f, "[rb{}]", ofst),
+      Operand::Relative(ofst) => write!(f, "[rb+{}]", ofst),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+  pub op: OpInfo,
+  pub operands: Vec<Operand>,
+}
+
+impl Instruction {
+  pub fn size(&self) -> usize {
+    self.op.size()
+  }
+
+  pub fn encode(&self) -> Vec<i64> {
+    let mut raw = vec![self.op.code];
+    let mut scale = 100;
+    for operand in &self.operands {
+      raw[0] += operand.mode() * scale;
+      raw.push(operand.value());
+      scale *= 10;
+    }
+    raw
+  }
+}
+
+impl fmt::Display for Instruction {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.op.name)?;
+    for (i, operand) in self.operands.iter().enumerate() {
+      write!(f, "{}{}", if i == 0 { " " } else { ", " }, operand)?;
+    }
+    Ok(())
+  }
+}
+
+// Decodes the instruction at `addr`. Anything the interpreter would refuse to
+// run (unknown opcode, bad or stray mode digits, immediate write, truncated
+// operands) yields None.
+pub fn decode(mem: &[i64], addr: usize) -> Option<Instruction> {
+  decode_by(mem, addr, |code| opcode_info(code).cloned())
+}
+
+// Same as `decode` with a machine's instruction set, registered opcodes
+// included
+pub fn decode_with<C: Cell>(mem: &[i64], addr: usize, opcodes: &Registry<C>) -> Option<Instruction> {
+  decode_by(mem, addr, |code| opcodes.get(code).map(|opcode| opcode.info()))
+}
+
+fn decode_by<F: Fn(i64) -> Option<OpInfo>>(mem: &[i64], addr: usize, lookup: F) -> Option<Instruction> {
+  let raw = *mem.get(addr)?;
+  if raw < 0 {
+    return None;
+  }
+  let op = lookup(raw % 100)?;
+  if addr + op.size() > mem.len() {
+    return None;
+  }
+
+  let mut modes = raw / 100;
+  let mut operands = Vec::new();
+  for (i, role) in op.params.iter().enumerate() {
+    let val = mem[addr + 1 + i];
+    let operand = match (modes % 10, role) {
+      (POSITION, _) => Operand::Position(val),
+      (IMMEDIATE, Role::Read) => Operand::Immediate(val),
+      (RELATIVE, _) => Operand::Relative(val),
+      _ => return None,
+    };
+    operands.push(operand);
+    modes /= 10;
+  }
+  if modes != 0 {
+    return None;
+  }
+
+  Some(Instruction { op: op, operands: operands })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+  Ins { addr: usize, ins: Instruction },
+  Data { addr: usize, value: i64 },
+}
+
+impl Line {
+  pub fn addr(&self) -> usize {
+    match *self {
+      Line::Ins { addr, .. } | Line::Data { addr, .. } => addr,
+    }
+  }
+
+  pub fn size(&self) -> usize {
+    match self {
+      Line::Ins { ins, .. } => ins.size(),
+      Line::Data { .. } => 1,
+    }
+  }
+}
+
+// Linear sweep from address 0, cells that don't decode become single `Data` lines
+pub fn disassemble(mem: &[i64]) -> Vec<Line> {
+  sweep(mem, |addr| decode(mem, addr))
+}
+
+// Same as `disassemble` with a machine's instruction set
+pub fn disassemble_with<C: Cell>(mem: &[i64], opcodes: &Registry<C>) -> Vec<Line> {
+  sweep(mem, |addr| decode_with(mem, addr, opcodes))
+}
+
+fn sweep<F: Fn(usize) -> Option<Instruction>>(mem: &[i64], decode_at: F) -> Vec<Line> {
+  let mut lines = Vec::new();
+  let mut addr = 0;
+  while addr < mem.len() {
+    let line = match decode_at(addr) {
+      Some(ins) => Line::Ins { addr: addr, ins: ins },
+      None => Line::Data { addr: addr, value: mem[addr] },
+    };
+    addr += line.size();
+    lines.push(line);
+  }
+  lines
+}
+
+// Annotated line: address, raw cells, decoded instruction
+pub fn format_line(mem: &[i64], line: &Line) -> String {
+  format_at(mem, 0, line)
+}
+
+// `mem` starting at address `base`
+fn format_at(mem: &[i64], base: usize, line: &Line) -> String {
+  let addr = line.addr();
+  let raw: Vec<String> = mem[addr..addr + line.size()].iter()
+    .map(|val| val.to_string())
+    .collect();
+  let text = match line {
+    Line::Ins { ins, .. } => ins.to_string(),
+    Line::Data { value, .. } => format!("DATA {}", value),
+  };
+  format!("{:>6}: {:<28} {}", base + addr, raw.join(","), text)
+}
+
+// Listing of a machine's allocated pages only, a write far out doesn't make
+// it list every address before it. Each run of consecutive pages is swept on
+// its own, the unallocated cells between them are all 0 and are skipped.
+pub fn listing_mem(mem: &Memory) -> String {
+  let mut runs: Vec<(usize, Vec<i64>)> = Vec::new();
+  for (idx, page) in mem.pages() {
+    let base = idx * PAGE_SIZE;
+    match runs.last_mut() {
+      Some((start, cells)) if *start + cells.len() == base => cells.extend_from_slice(page),
+      _ => runs.push((base, page.to_vec())),
+    }
+  }
+  let mut out = String::new();
+  let mut next = 0;
+  let last = runs.len().saturating_sub(1);
+  for (i, (base, mut cells)) in runs.into_iter().enumerate() {
+    if i == last {
+      // A page of zeros can lie past the end
+      cells.truncate(mem.len().saturating_sub(base));
+    } else {
+      // Zeros up to the next run read the same as the gap after them
+      let used = cells.iter().rposition(|&val| val != 0).map_or(0, |i| i + 1);
+      cells.truncate(used);
+    }
+    if base > next {
+      out.push_str(&format!("{:>6}  ({} unallocated cells)\n", "...", base - next));
+    }
+    for line in disassemble(&cells) {
+      out.push_str(&format_at(&cells, base, &line));
+      out.push('\n');
+    }
+    next = base + cells.len();
+  }
+  out
+}
+
+// Assembler source for `mem`, `asm::assemble` turns it back into the same cells
+pub fn source(mem: &[i64]) -> String {
+  let mut out = String::new();
+  for line in disassemble(mem) {
+    let text = match line {
+      Line::Ins { ins, .. } => ins.to_string(),
+      Line::Data { value, .. } => format!(".data {}", value),
+    };
+    out.push_str(&format!("  {}\n", text));
+  }
+  out
+}
+
+// Same as `decode_with`, for a running machine's memory. Cells that don't fit
+// in an i64 can't be part of an instruction.
+pub fn decode_mem<C: Cell>(mem: &Memory<C>, opcodes: &Registry<C>, addr: usize) -> Option<Instruction> {
+  let window: Vec<i64> = (addr..addr + 4).map_while(|a| mem[a].to_i64()).collect();
+  decode_with(&window, 0, opcodes)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn listing_skips_unallocated_pages() {
+    let mut mem = Memory::from(vec![1101, 2, 3, 7, 99]);
+    mem.set(1_000_000_000_000, 99);
+    let listing = listing_mem(&mem);
+    let far_page = 1_000_000_000_000 / PAGE_SIZE * PAGE_SIZE;
+    let lines: Vec<&str> = listing.lines().collect();
+    // add, halt, the gap, then the zeros before the write and the write
+    assert_eq!(lines.len(), 3 + (1_000_000_000_000 - far_page) + 1);
+    assert!(lines[0].ends_with("add #2, #3, [7]"));
+    assert_eq!(lines[2], format!("{:>6}  ({} unallocated cells)", "...", far_page - 5));
+    assert!(listing.trim_end().ends_with("halt"));
+  }
+}
@@ -0,0 +1,102 @@
+use std::error;
+use std::fmt;
+use std::result;
+
+// Every variant records the instruction pointer and the raw instruction
+// (opcode with its mode digits) that was being executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntcodeError {
+  UnknownOpcode { iptr: usize, ins: i64 },
+  BadMode { iptr: usize, ins: i64, param: usize, mode: i64 },
+  ImmediateWrite { iptr: usize, ins: i64, param: usize },
+  NegativeAddress { iptr: usize, ins: i64, addr: i64 },
+  NegativeRelativeBase { iptr: usize, ins: i64, rptr: i64 },
+  InputExhausted { iptr: usize, ins: i64 },
+  // Exceeded one of the machine's `Limits`
+  AddressLimit { iptr: usize, ins: i64, addr: i64, limit: usize },
+  OutputLimit { iptr: usize, ins: i64, limit: u64 },
+  RelativeBaseLimit { iptr: usize, ins: i64, rptr: i64, limit: usize },
+  OutOfFuel { iptr: usize, ins: i64 },
+  // A result didn't fit in the cell type
+  Overflow { iptr: usize, ins: i64 },
+  // A value used as an address or relative base doesn't fit in an i64
+  OutOfRange { iptr: usize, ins: i64 },
+  // Raised by the handler of a registered opcode
+  Fault { iptr: usize, ins: i64, msg: &'static str },
+}
+
+pub type Result<T> = result::Result<T, IntcodeError>;
+
+impl IntcodeError {
+  pub fn iptr(&self) -> usize {
+    match *self {
+      IntcodeError::UnknownOpcode { iptr, .. } |
+      IntcodeError::BadMode { iptr, .. } |
+      IntcodeError::ImmediateWrite { iptr, .. } |
+      IntcodeError::NegativeAddress { iptr, .. } |
+      IntcodeError::NegativeRelativeBase { iptr, .. } |
+      IntcodeError::InputExhausted { iptr, .. } |
+      IntcodeError::AddressLimit { iptr, .. } |
+      IntcodeError::OutputLimit { iptr, .. } |
+      IntcodeError::RelativeBaseLimit { iptr, .. } |
+      IntcodeError::OutOfFuel { iptr, .. } |
+      IntcodeError::Overflow { iptr, .. } |
+      IntcodeError::OutOfRange { iptr, .. } |
+      IntcodeError::Fault { iptr, .. } => iptr,
+    }
+  }
+
+  pub fn ins(&self) -> i64 {
+    match *self {
+      IntcodeError::UnknownOpcode { ins, .. } |
+      IntcodeError::BadMode { ins, .. } |
+      IntcodeError::ImmediateWrite { ins, .. } |
+      IntcodeError::NegativeAddress { ins, .. } |
+      IntcodeError::NegativeRelativeBase { ins, .. } |
+      IntcodeError::InputExhausted { ins, .. } |
+      IntcodeError::AddressLimit { ins, .. } |
+      IntcodeError::OutputLimit { ins, .. } |
+      IntcodeError::RelativeBaseLimit { ins, .. } |
+      IntcodeError::OutOfFuel { ins, .. } |
+      IntcodeError::Overflow { ins, .. } |
+      IntcodeError::OutOfRange { ins, .. } |
+      IntcodeError::Fault { ins, .. } => ins,
+    }
+  }
+}
+
+impl fmt::Display for IntcodeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      IntcodeError::UnknownOpcode { .. } =>
+        write!(f, "unknown opcode {}", self.ins() % 100)?,
+      IntcodeError::BadMode { param, mode, .. } =>
+        write!(f, "invalid mode {} for parameter {}", mode, param)?,
+      IntcodeError::ImmediateWrite { param, .. } =>
+        write!(f, "write through immediate mode parameter {}", param)?,
+      IntcodeError::NegativeAddress { addr, .. } =>
+        write!(f, "negative address {}", addr)?,
+      IntcodeError::NegativeRelativeBase { rptr, .. } =>
+        write!(f, "relative base would become {}", rptr)?,
+      IntcodeError::InputExhausted { .. } =>
+        write!(f, "input exhausted")?,
+      IntcodeError::AddressLimit { addr, limit, .. } =>
+        write!(f, "address {} above limit {}", addr, limit)?,
+      IntcodeError::OutputLimit { limit, .. } =>
+        write!(f, "output limit of {} reached", limit)?,
+      IntcodeError::RelativeBaseLimit { rptr, limit, .. } =>
+        write!(f, "relative base would become {}, above limit {}", rptr, limit)?,
+      IntcodeError::OutOfFuel { .. } =>
+        write!(f, "out of fuel")?,
+      IntcodeError::Overflow { .. } =>
+        write!(f, "arithmetic overflow")?,
+      IntcodeError::OutOfRange { .. } =>
+        write!(f, "value out of range")?,
+      IntcodeError::Fault { msg, .. } =>
+        write!(f, "{}", msg)?,
+    }
+    write!(f, " at {} (instruction {})", self.iptr(), self.ins())
+  }
+}
+
+impl error::Error for IntcodeError {}
@@ -0,0 +1,329 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use super::{IntcodeError, Interpreter, Result, State};
+
+// Steps a machine may run per poll before it yields to the executor, so one
+// busy machine can't starve the others
+const STEPS_PER_POLL: usize = 10000;
+
+// Same shape as `futures::Stream`, which we don't depend on
+pub trait Stream {
+  type Item;
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>>;
+}
+
+struct Shared {
+  queue: VecDeque<i64>,
+  waker: Option<Waker>,
+  senders: usize,
+}
+
+// Unbounded channel whose receiving end can be awaited
+pub fn channel() -> (AsyncSender, AsyncReceiver) {
+  let shared = Arc::new(Mutex::new(Shared {
+    queue: VecDeque::new(),
+    waker: None,
+    senders: 1,
+  }));
+  (AsyncSender { shared: shared.clone() }, AsyncReceiver { shared: shared })
+}
+
+pub struct AsyncSender {
+  shared: Arc<Mutex<Shared>>,
+}
+
+impl AsyncSender {
+  pub fn send(&self, val: i64) {
+    let mut shared = self.shared.lock().unwrap();
+    shared.queue.push_back(val);
+    if let Some(waker) = shared.waker.take() {
+      waker.wake();
+    }
+  }
+}
+
+impl Clone for AsyncSender {
+  fn clone(&self) -> AsyncSender {
+    self.shared.lock().unwrap().senders += 1;
+    AsyncSender { shared: self.shared.clone() }
+  }
+}
+
+impl Drop for AsyncSender {
+  fn drop(&mut self) {
+    let mut shared = self.shared.lock().unwrap();
+    shared.senders -= 1;
+    // The receiver has to find out that nothing more is coming
+    if shared.senders == 0 {
+      if let Some(waker) = shared.waker.take() {
+        waker.wake();
+      }
+    }
+  }
+}
+
+pub struct AsyncReceiver {
+  shared: Arc<Mutex<Shared>>,
+}
+
+impl AsyncReceiver {
+  // Ready(None) once the queue is empty and every sender is gone
+  pub fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<i64>> {
+    let mut shared = self.shared.lock().unwrap();
+    match shared.queue.pop_front() {
+      Some(val) => Poll::Ready(Some(val)),
+      None if shared.senders == 0 => Poll::Ready(None),
+      None => {
+        shared.waker = Some(cx.waker().clone());
+        Poll::Pending
+      },
+    }
+  }
+}
+
+impl Stream for AsyncReceiver {
+  type Item = i64;
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<i64>> {
+    self.get_mut().poll_recv(cx)
+  }
+}
+
+// A machine whose outputs form a stream. Inputs come from `stdin` first and
+// are then awaited on the receiver. The stream ends when the machine halts,
+// faults, runs out of fuel, or needs input after every sender is gone.
+//
+// Attached `input`/`output` devices are bypassed, outputs don't pile up in
+// `stdout` either.
+pub struct AsyncInterpreter {
+  vm: Interpreter,
+  input: AsyncReceiver,
+  error: Option<IntcodeError>,
+}
+
+impl AsyncInterpreter {
+  pub fn new(vm: Interpreter, input: AsyncReceiver) -> AsyncInterpreter {
+    AsyncInterpreter {
+      vm: vm,
+      input: input,
+      error: None,
+    }
+  }
+
+  pub fn interpreter(&self) -> &Interpreter {
+    &self.vm
+  }
+
+  // Why the stream ended early, if it did
+  pub fn error(&self) -> Option<&IntcodeError> {
+    self.error.as_ref()
+  }
+
+  pub fn into_inner(self) -> (Interpreter, Option<IntcodeError>) {
+    (self.vm, self.error)
+  }
+
+  // Turns the machine into a future that forwards its outputs to `output`
+  pub fn forward(self, output: AsyncSender) -> Machine {
+    Machine {
+      stream: Some(self),
+      output: output,
+    }
+  }
+}
+
+impl Stream for AsyncInterpreter {
+  type Item = i64;
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<i64>> {
+    let this = self.get_mut();
+    if this.error.is_some() {
+      return Poll::Ready(None);
+    }
+    for _ in 0..STEPS_PER_POLL {
+      let res = this.vm.step_with(None, Some(&mut |_| ()));
+      match res {
+        Ok(State::OutputReady(out)) => return Poll::Ready(Some(out)),
+        Ok(State::AwaitingInput) => match this.input.poll_recv(cx) {
+          Poll::Ready(Some(inp)) => this.vm.stdin.push_back(inp),
+          Poll::Ready(None) => {
+            this.error = Some(IntcodeError::InputExhausted { iptr: this.vm.iptr, ins: this.vm.mem[this.vm.iptr] });
+            return Poll::Ready(None);
+          },
+          Poll::Pending => return Poll::Pending,
+        },
+        Ok(State::OutOfFuel) => {
+          this.error = Some(IntcodeError::OutOfFuel { iptr: this.vm.iptr, ins: this.vm.mem[this.vm.iptr] });
+          return Poll::Ready(None);
+        },
+        Ok(State::Halted) => return Poll::Ready(None),
+        Ok(_) => (),
+        Err(e) => {
+          this.error = Some(e);
+          return Poll::Ready(None);
+        },
+      }
+    }
+    cx.waker().wake_by_ref();
+    Poll::Pending
+  }
+}
+
+// Future of a machine wired between two channels, resolves to the final
+// machine once it halts
+pub struct Machine {
+  stream: Option<AsyncInterpreter>,
+  output: AsyncSender,
+}
+
+impl Future for Machine {
+  type Output = Result<Interpreter>;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<Interpreter>> {
+    let this = self.get_mut();
+    let stream = this.stream.as_mut().expect("Machine polled after completion!");
+    loop {
+      match Pin::new(&mut *stream).poll_next(cx) {
+        Poll::Ready(Some(out)) => this.output.send(out),
+        Poll::Ready(None) => break,
+        Poll::Pending => return Poll::Pending,
+      }
+    }
+    let (vm, error) = this.stream.take().unwrap().into_inner();
+    Poll::Ready(match error {
+      Some(e) => Err(e),
+      None => Ok(vm),
+    })
+  }
+}
+
+struct ThreadWaker {
+  thread: Thread,
+  woken: AtomicBool,
+}
+
+impl Wake for ThreadWaker {
+  fn wake(self: Arc<Self>) {
+    self.wake_by_ref();
+  }
+
+  fn wake_by_ref(self: &Arc<Self>) {
+    self.woken.store(true, Ordering::SeqCst);
+    self.thread.unpark();
+  }
+}
+
+fn thread_waker() -> (Arc<ThreadWaker>, Waker) {
+  let inner = Arc::new(ThreadWaker {
+    thread: thread::current(),
+    woken: AtomicBool::new(false),
+  });
+  (inner.clone(), Waker::from(inner))
+}
+
+// Polls `fut` on the current thread until it completes
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+  let mut fut = Box::pin(fut);
+  let (inner, waker) = thread_waker();
+  let mut cx = Context::from_waker(&waker);
+  loop {
+    inner.woken.store(false, Ordering::SeqCst);
+    if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+      return out;
+    }
+    while !inner.woken.load(Ordering::SeqCst) {
+      thread::park();
+    }
+  }
+}
+
+// Runs all machines round-robin on the current thread. Machines that are
+// still waiting for input when nothing can make progress any more are
+// reported as None.
+pub fn run_all(machines: Vec<Machine>) -> Vec<Option<Result<Interpreter>>> {
+  let mut machines: Vec<Pin<Box<Machine>>> = machines.into_iter().map(Box::pin).collect();
+  let mut results: Vec<Option<Result<Interpreter>>> = machines.iter().map(|_| None).collect();
+  let (inner, waker) = thread_waker();
+  let mut cx = Context::from_waker(&waker);
+  loop {
+    inner.woken.store(false, Ordering::SeqCst);
+    let mut pending = 0;
+    for (i, machine) in machines.iter_mut().enumerate() {
+      if results[i].is_some() {
+        continue;
+      }
+      match machine.as_mut().poll(&mut cx) {
+        Poll::Ready(res) => results[i] = Some(res),
+        Poll::Pending => pending += 1,
+      }
+    }
+    // The machines only feed each other, a round that woke nobody means
+    // none of them can make progress again
+    if pending == 0 || !inner.woken.load(Ordering::SeqCst) {
+      return results;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Reads a value, outputs it plus one and repeats while that is below 10
+  const COUNTER: [i64; 16] = [3, 20, 1001, 20, 1, 20, 4, 20, 1007, 20, 10, 21, 1005, 21, 0, 99];
+
+  #[test]
+  fn ring() {
+    let (to_a, from_b) = channel();
+    let (to_b, from_a) = channel();
+    let mut a = Interpreter::new(COUNTER.to_vec());
+    a.stdin.push_back(0);
+    let machines = vec![
+      AsyncInterpreter::new(a, from_b).forward(to_b),
+      AsyncInterpreter::new(Interpreter::new(COUNTER.to_vec()), from_a).forward(to_a),
+    ];
+    let results = run_all(machines);
+    let cells: Vec<i64> = results.into_iter()
+      .map(|res| res.expect("machine still waiting").expect("machine failed").mem[20])
+      .collect();
+    // B counted to 10 and stopped, A answered once more
+    assert_eq!(cells, vec![11, 10]);
+  }
+
+  #[test]
+  fn deadlock() {
+    let (to_a, from_b) = channel();
+    let (to_b, from_a) = channel();
+    let machines = vec![
+      AsyncInterpreter::new(Interpreter::new(COUNTER.to_vec()), from_b).forward(to_b),
+      AsyncInterpreter::new(Interpreter::new(COUNTER.to_vec()), from_a).forward(to_a),
+    ];
+    assert!(run_all(machines).iter().all(|res| res.is_none()));
+  }
+
+  #[test]
+  fn closed_sender() {
+    let (tx, rx) = channel();
+    let (out, mut outputs) = channel();
+    tx.send(1);
+    drop(tx);
+    // Outputs its input, then asks for one more
+    let vm = Interpreter::new(vec![3, 0, 4, 0, 3, 0, 99]);
+    match block_on(AsyncInterpreter::new(vm, rx).forward(out)) {
+      Err(IntcodeError::InputExhausted { iptr: 4, .. }) => (),
+      res => panic!("expected InputExhausted, got {:?}", res.map(|vm| vm.state)),
+    }
+    let (_, waker) = thread_waker();
+    let mut cx = Context::from_waker(&waker);
+    assert_eq!(outputs.poll_recv(&mut cx), Poll::Ready(Some(1)));
+    // The machine's sender went with it
+    assert_eq!(outputs.poll_recv(&mut cx), Poll::Ready(None));
+  }
+}
@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+
+use super::{Cell, Interpreter, State};
+
+// Everything needed to undo one step
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry<C = i64> {
+  pub step: u64,
+  pub iptr: usize,
+  pub rptr: usize,
+  pub state: State<C>,
+  // Every cell the step wrote, in order: address, old value, new value
+  pub writes: Vec<(usize, C, C)>,
+  pub input: Option<C>,
+  pub output: Option<C>,
+  // Whether `output` was appended to `stdout` rather than sent to a device
+  pub to_stdout: bool,
+  pub fuel: Option<u64>,
+}
+
+// Undo log of the most recent steps. Once `capacity` entries are kept the
+// oldest ones are forgotten, so long runs stay bounded.
+#[derive(Debug, Clone)]
+pub struct Journal<C = i64> {
+  entries: VecDeque<Entry<C>>,
+  capacity: usize,
+  steps: u64,
+}
+
+impl<C: Cell> Journal<C> {
+  pub fn new(capacity: usize) -> Journal<C> {
+    Journal {
+      entries: VecDeque::new(),
+      capacity: capacity,
+      steps: 0,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  // Number of steps executed since the journal was attached
+  pub fn steps(&self) -> u64 {
+    self.steps
+  }
+
+  pub fn entries(&self) -> impl Iterator<Item = &Entry<C>> {
+    self.entries.iter()
+  }
+
+  // Most recent step that wrote `addr`, as far back as the journal reaches
+  pub fn last_write(&self, addr: usize) -> Option<&Entry<C>> {
+    self.entries.iter().rev().find(|entry| entry.writes.iter().any(|&(a, _, _)| a == addr))
+  }
+
+  pub(super) fn before(&self, vm: &Interpreter<C>) -> Entry<C> {
+    Entry {
+      step: self.steps,
+      iptr: vm.iptr,
+      rptr: vm.rptr,
+      state: vm.state.clone(),
+      writes: Vec::new(),
+      input: None,
+      output: None,
+      to_stdout: false,
+      fuel: vm.fuel,
+    }
+  }
+
+  // Completes the entry with what the step did and records it
+  pub(super) fn push(&mut self, mut entry: Entry<C>, stdout_len: usize, vm: &Interpreter<C>) {
+    entry.to_stdout = vm.stdout.len() > stdout_len;
+    entry.writes = vm.last_writes.clone();
+    entry.input = vm.last_input.clone();
+    entry.output = vm.last_output.clone();
+
+    self.steps += 1;
+    if self.capacity == 0 {
+      return;
+    }
+    if self.entries.len() == self.capacity {
+      self.entries.pop_front();
+    }
+    self.entries.push_back(entry);
+  }
+
+  pub(super) fn pop(&mut self) -> Option<Entry<C>> {
+    let entry = self.entries.pop_back()?;
+    self.steps -= 1;
+    Some(entry)
+  }
+}
+
+impl<C: Cell> Interpreter<C> {
+  // Undoes the last journaled step. Inputs go back to the front of `stdin`,
+  // even if they came from a device. Outputs the host already took from
+  // `stdout` or that went to a device can't be taken back.
+  pub fn step_back(&mut self) -> bool {
+    let entry = match self.journal.as_mut().and_then(|journal| journal.pop()) {
+      Some(entry) => entry,
+      None => return false,
+    };
+    for (addr, old, _) in entry.writes.into_iter().rev() {
+      self.mem[addr] = old;
+    }
+    if let Some(inp) = entry.input {
+      self.stdin.push_front(inp);
+    }
+    if entry.output.is_some() {
+      self.outputs -= 1;
+      // Later outputs were undone first, so it's the last one unless the
+      // host took it
+      if entry.to_stdout {
+        self.stdout.pop_back();
+      }
+    }
+    self.iptr = entry.iptr;
+    self.rptr = entry.rptr;
+    self.state = entry.state;
+    self.fuel = entry.fuel;
+    true
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn undo_device_output() {
+    let mut vm = Interpreter::new(vec![104, 7, 104, 7, 99]);
+    vm.journal = Some(Journal::new(10));
+    assert_eq!(vm.step(), Ok(State::OutputReady(7)));
+    let mut sink: Vec<i64> = Vec::new();
+    vm.step_with(None, Some(&mut sink)).unwrap();
+    assert_eq!(sink, vec![7]);
+    // The 7 in stdout came from the first step and stays
+    assert!(vm.step_back());
+    assert_eq!(vm.stdout, vec![7]);
+    assert!(vm.step_back());
+    assert!(vm.stdout.is_empty());
+    assert_eq!(vm.output_count(), 0);
+  }
+
+  #[test]
+  fn undo_restores_fuel() {
+    let mut vm = Interpreter::new(vec![1101, 1, 2, 0, 99]);
+    vm.journal = Some(Journal::new(10));
+    vm.fuel = Some(5);
+    vm.step().unwrap();
+    assert_eq!(vm.fuel, Some(4));
+    assert!(vm.step_back());
+    assert_eq!(vm.fuel, Some(5));
+    assert_eq!(vm.mem[0], 1101);
+  }
+}
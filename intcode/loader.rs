@@ -0,0 +1,169 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use super::snapshot::{self, SnapshotError};
+use super::Interpreter;
+
+// Programs are integers separated by commas, whitespace or both, e.g.
+//
+//   # count down from 10
+//   1101,0,10,20,
+//   4,20, 1001,20,-1,20, 1005,20,4,   # loop
+//   99
+//
+// `#` starts a comment that runs to the end of the line. A trailing comma is
+// fine, an empty value between two commas is not.
+#[derive(Debug)]
+pub enum LoadError {
+  Io { source: String, error: io::Error },
+  Empty { source: String },
+  // Bytes from the start of the input
+  InvalidValue { source: String, offset: usize, token: String },
+  MissingValue { source: String, offset: usize },
+  Snapshot { source: String, error: SnapshotError },
+}
+
+impl fmt::Display for LoadError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LoadError::Io { source, error } => write!(f, "{}: {}", source, error),
+      LoadError::Empty { source } => write!(f, "{}: no program", source),
+      LoadError::InvalidValue { source, offset, token } =>
+        write!(f, "{}: invalid value `{}` at byte {}", source, token, offset),
+      LoadError::MissingValue { source, offset } =>
+        write!(f, "{}: missing value before the comma at byte {}", source, offset),
+      LoadError::Snapshot { source, error } => write!(f, "{}: {}", source, error),
+    }
+  }
+}
+
+impl error::Error for LoadError {}
+
+pub const STDIN: &str = "<stdin>";
+
+// `source` names the text in errors, e.g. the file it came from
+pub fn parse_program(source: &str, text: &str) -> Result<Vec<i64>, LoadError> {
+  let bytes = text.as_bytes();
+  let mut program = Vec::new();
+  // Nothing but separators since the last comma, or since the start
+  let mut after_comma = true;
+  let mut i = 0;
+  while i < bytes.len() {
+    let c = bytes[i];
+    if c.is_ascii_whitespace() {
+      i += 1;
+    } else if c == b'#' {
+      while i < bytes.len() && bytes[i] != b'\n' {
+        i += 1;
+      }
+    } else if c == b',' {
+      if after_comma {
+        return Err(LoadError::MissingValue { source: source.to_string(), offset: i });
+      }
+      after_comma = true;
+      i += 1;
+    } else {
+      let start = i;
+      while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b',' && bytes[i] != b'#' {
+        i += 1;
+      }
+      let token = &text[start..i];
+      match token.parse::<i64>() {
+        Ok(val) => program.push(val),
+        Err(_) => return Err(LoadError::InvalidValue { source: source.to_string(), offset: start, token: token.to_string() }),
+      }
+      after_comma = false;
+    }
+  }
+  if program.is_empty() {
+    return Err(LoadError::Empty { source: source.to_string() });
+  }
+  Ok(program)
+}
+
+// Text at `path` and its name in errors, stdin for a path of `-`
+fn read_source(path: &Path) -> Result<(String, String), LoadError> {
+  if path == Path::new("-") {
+    let mut text = String::new();
+    return match io::stdin().lock().read_to_string(&mut text) {
+      Ok(_) => Ok((STDIN.to_string(), text)),
+      Err(e) => Err(LoadError::Io { source: STDIN.to_string(), error: e }),
+    };
+  }
+  let source = path.display().to_string();
+  match fs::read_to_string(path) {
+    Ok(text) => Ok((source, text)),
+    Err(e) => Err(LoadError::Io { source: source, error: e }),
+  }
+}
+
+// Reads stdin for a path of `-`
+pub fn load_program<P: AsRef<Path>>(path: P) -> Result<Vec<i64>, LoadError> {
+  let (source, text) = read_source(path.as_ref())?;
+  parse_program(&source, &text)
+}
+
+// A machine from either a program or a snapshot
+pub fn load_machine<P: AsRef<Path>>(path: P) -> Result<Interpreter, LoadError> {
+  let (source, text) = read_source(path.as_ref())?;
+  if snapshot::is_snapshot(&text) {
+    return Interpreter::load(text.as_bytes())
+      .map_err(|e| LoadError::Snapshot { source: source, error: e });
+  }
+  Ok(Interpreter::new(parse_program(&source, &text)?))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(text: &str) -> Result<Vec<i64>, LoadError> {
+    parse_program("test", text)
+  }
+
+  #[test]
+  fn separators_and_comments() {
+    let text = "# count down\n1101,0,10,20,\n4,20 1001,20,-1,20,  # loop\n99,\n";
+    assert_eq!(parse(text).unwrap(), vec![1101, 0, 10, 20, 4, 20, 1001, 20, -1, 20, 99]);
+    assert_eq!(parse("1,2#,,x\n,3").unwrap(), vec![1, 2, 3]);
+  }
+
+  #[test]
+  fn empty() {
+    for text in &["", "  \n", "# nothing\n"] {
+      match parse(text) {
+        Err(LoadError::Empty { source }) => assert_eq!(source, "test"),
+        res => panic!("expected Empty for {:?}, got {:?}", text, res),
+      }
+    }
+  }
+
+  #[test]
+  fn missing_value() {
+    match parse("1,2,,3") {
+      Err(LoadError::MissingValue { offset: 4, .. }) => (),
+      res => panic!("expected MissingValue at 4, got {:?}", res),
+    }
+    match parse("  ,1") {
+      Err(LoadError::MissingValue { offset: 2, .. }) => (),
+      res => panic!("expected MissingValue at 2, got {:?}", res),
+    }
+  }
+
+  #[test]
+  fn invalid_value() {
+    let err = parse("1,2,\n3x,4").unwrap_err();
+    match &err {
+      LoadError::InvalidValue { offset: 5, token, .. } => assert_eq!(token, "3x"),
+      _ => panic!("expected InvalidValue at 5, got {:?}", err),
+    }
+    assert_eq!(err.to_string(), "test: invalid value `3x` at byte 5");
+    match parse("1,99999999999999999999") {
+      Err(LoadError::InvalidValue { offset: 2, .. }) => (),
+      res => panic!("expected InvalidValue at 2, got {:?}", res),
+    }
+  }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::mem;
+use std::ops;
+
+use super::cell::Cell;
+use super::decode::{DecodeCache, Decoded};
+
+const PAGE_BITS: usize = 10;
+pub const PAGE_SIZE: usize = 1 << PAGE_BITS;
+const PAGE_MASK: usize = PAGE_SIZE - 1;
+
+// Paged memory, pages are only allocated once something is written to them.
+// Reading an address that was never written yields 0.
+//
+// Instructions are decoded once and cached, every write drops the cached
+// instructions it overlaps so self-modifying programs see their changes.
+#[derive(Debug, Clone)]
+pub struct Memory<C: Cell = i64> {
+  pages: HashMap<usize, Box<[C]>>,
+  // One past the highest address that was loaded or written
+  len: usize,
+  decoded: Option<DecodeCache>,
+  // What unallocated addresses read as
+  zero: C,
+}
+
+impl<C: Cell> Default for Memory<C> {
+  fn default() -> Memory<C> {
+    Memory {
+      pages: HashMap::new(),
+      len: 0,
+      decoded: Some(DecodeCache::new()),
+      zero: C::from_i64(0),
+    }
+  }
+}
+
+impl<C: Cell> Memory<C> {
+  pub fn new() -> Memory<C> {
+    Memory::default()
+  }
+
+  pub fn get(&self, addr: usize) -> C {
+    self[addr].clone()
+  }
+
+  pub fn set(&mut self, addr: usize, val: C) {
+    *self.cell_mut(addr) = val;
+  }
+
+  // Same as `set`, returning the value that was there
+  pub fn replace(&mut self, addr: usize, val: C) -> C {
+    mem::replace(self.cell_mut(addr), val)
+  }
+
+  fn cell_mut(&mut self, addr: usize) -> &mut C {
+    if let Some(cache) = self.decoded.as_mut() {
+      cache.invalidate(addr);
+    }
+    if addr >= self.len {
+      self.len = addr + 1;
+    }
+    let zero = &self.zero;
+    let page = self.pages.entry(addr >> PAGE_BITS)
+      .or_insert_with(|| vec![zero.clone(); PAGE_SIZE].into_boxed_slice());
+    &mut page[addr & PAGE_MASK]
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn allocated_pages(&self) -> usize {
+    self.pages.len()
+  }
+
+  // Instruction at `addr`, from the cache when it is on
+  pub fn decode(&mut self, addr: usize) -> Decoded {
+    if let Some(ins) = self.decoded.as_ref().and_then(|cache| cache.get(addr)) {
+      return ins;
+    }
+    let ins = match self[addr].to_i64() {
+      Some(raw) => Decoded::new(raw),
+      None => Decoded::unknown(),
+    };
+    if let Some(cache) = self.decoded.as_mut() {
+      cache.insert(addr, ins);
+    }
+    ins
+  }
+
+  pub fn set_decode_cache(&mut self, on: bool) {
+    self.decoded = if on { Some(DecodeCache::new()) } else { None };
+  }
+
+  // Dense copy of addresses `0..len()`
+  pub fn to_vec(&self) -> Vec<C> {
+    (0..self.len).map(|addr| self.get(addr)).collect()
+  }
+}
+
+impl<C: Cell> From<Vec<C>> for Memory<C> {
+  fn from(program: Vec<C>) -> Memory<C> {
+    let mut mem = Memory::new();
+    for (addr, val) in program.into_iter().enumerate() {
+      mem.set(addr, val);
+    }
+    mem
+  }
+}
+
+impl<C: Cell> ops::Index<usize> for Memory<C> {
+  type Output = C;
+
+  fn index(&self, addr: usize) -> &Self::Output {
+    match self.pages.get(&(addr >> PAGE_BITS)) {
+      Some(page) => &page[addr & PAGE_MASK],
+      None => &self.zero,
+    }
+  }
+}
+
+impl<C: Cell> ops::IndexMut<usize> for Memory<C> {
+  fn index_mut(&mut self, addr: usize) -> &mut Self::Output {
+    self.cell_mut(addr)
+  }
+}
+
+// Raw page access for snapshots
+impl<C: Cell> Memory<C> {
+  // Allocated pages by index, in address order
+  pub(super) fn pages(&self) -> Vec<(usize, &[C])> {
+    let mut pages: Vec<(usize, &[C])> = self.pages.iter()
+      .map(|(idx, page)| (*idx, &page[..]))
+      .collect();
+    pages.sort_by_key(|(idx, _)| *idx);
+    pages
+  }
+
+  pub(super) fn load_page(&mut self, idx: usize, cells: &[C]) {
+    let mut page = vec![self.zero.clone(); PAGE_SIZE];
+    page[..cells.len()].clone_from_slice(cells);
+    self.pages.insert(idx, page.into_boxed_slice());
+    if let Some(cache) = self.decoded.as_mut() {
+      cache.clear();
+    }
+  }
+
+  pub(super) fn set_len(&mut self, len: usize) {
+    self.len = len;
+  }
+}
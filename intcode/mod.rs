@@ -0,0 +1,617 @@
+// Shared Intcode virtual machine, linked by every day with `pub mod intcode;`.
+// Being public, its API counts as used in days that only need part of it, dead
+// private code still warns.
+
+pub mod ascii;
+pub mod asm;
+pub mod bigint;
+pub mod cell;
+pub mod cfg;
+pub mod conformance;
+pub mod debugger;
+pub mod decode;
+pub mod device;
+pub mod disasm;
+mod error;
+pub mod future;
+pub mod journal;
+pub mod loader;
+mod memory;
+pub mod network;
+pub mod profile;
+pub mod registry;
+pub mod snapshot;
+pub mod trace;
+pub mod transpile;
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+pub use self::cell::Cell;
+pub use self::decode::Decoded;
+pub use self::device::{InputSource, OutputSink};
+pub use self::error::{IntcodeError, Result};
+pub use self::journal::Journal;
+pub use self::loader::{load_machine, load_program, parse_program, LoadError};
+pub use self::memory::{Memory, PAGE_SIZE};
+pub use self::network::Network;
+pub use self::profile::Profile;
+pub use self::registry::{Exec, Flow, Registry};
+pub use self::trace::Tracer;
+
+pub const ADD_INS: i64 = 1;
+pub const ADD_SIZE: usize = 4;
+pub const MULT_INS: i64 = 2;
+pub const MULT_SIZE: usize = 4;
+pub const INP_INS: i64 = 3;
+pub const INP_SIZE: usize = 2;
+pub const OUT_INS: i64 = 4;
+pub const OUT_SIZE: usize = 2;
+pub const JMPT_INS: i64 = 5;
+pub const JMPT_SIZE: usize = 3;
+pub const JMPF_INS: i64 = 6;
+pub const JMPF_SIZE: usize = 3;
+pub const TLS_INS: i64 = 7;
+pub const TLS_SIZE: usize = 4;
+pub const TEQ_INS: i64 = 8;
+pub const TEQ_SIZE: usize = 4;
+pub const SRL_INS: i64 = 9;
+pub const SRL_SIZE: usize = 2;
+pub const HALT_INS: i64 = 99;
+pub const HALT_SIZE: usize = 1;
+
+const TRIMMERS: [i64; 10] = [1, 10, 100, 1000, 10000, 100000, 1000000, 10000000, 100000000, 1000000000];
+pub fn digit_at(n: i64, i: usize) -> i64 {
+  (n / TRIMMERS[i]) % 10
+}
+
+pub const POSITION: i64 = 0;
+pub const IMMEDIATE: i64 = 1;
+pub const RELATIVE: i64 = 2;
+
+// `C` is the machine's cell type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State<C = i64> {
+  Idle,
+  Running,
+  // The last step produced this output
+  OutputReady(C),
+  // Stopped at an input instruction with nothing to read
+  AwaitingInput,
+  // Used up its `fuel`, continues once it gets more
+  OutOfFuel,
+  Halted,
+}
+
+impl<C> State<C> {
+  pub fn can_continue(&self) -> bool {
+    match self {
+      State::Idle | State::Running | State::OutputReady(_) => true,
+      State::AwaitingInput | State::OutOfFuel | State::Halted => false,
+    }
+  }
+}
+
+// Bounds for running untrusted programs, exceeding one is an error. All
+// unlimited by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+  // Highest address that may be read, written or jumped to
+  pub max_addr: Option<usize>,
+  // Number of outputs the machine may produce
+  pub max_outputs: Option<u64>,
+  pub max_rptr: Option<usize>,
+}
+
+// What `ADD_INS` and `MULT_INS` do with results that don't fit in a cell
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Arithmetic {
+  // Two's complement wrap-around
+  #[default]
+  Wrapping,
+  // Stops with `IntcodeError::Overflow`
+  Checked,
+}
+
+// Instruction set as the puzzle grew it. The old per-day copies each bent
+// the rules differently, day02 skipped unknown opcodes and day05 read its
+// inputs newest first, the levels follow the puzzle text instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Isa {
+  // ADD_INS, MULT_INS and HALT_INS in position mode
+  Day02,
+  // Adds input, output, jumps and compares, and immediate mode
+  Day05,
+  // Adds SRL_INS and relative mode
+  #[default]
+  Day09,
+}
+
+impl Isa {
+  pub fn has_opcode(&self, op: i64) -> bool {
+    match op {
+      ADD_INS | MULT_INS | HALT_INS => true,
+      INP_INS | OUT_INS | JMPT_INS | JMPF_INS | TLS_INS | TEQ_INS => *self >= Isa::Day05,
+      SRL_INS => *self >= Isa::Day09,
+      _ => false,
+    }
+  }
+
+  pub fn has_mode(&self, mode: i64) -> bool {
+    match mode {
+      POSITION => true,
+      IMMEDIATE => *self >= Isa::Day05,
+      RELATIVE => *self >= Isa::Day09,
+      _ => false,
+    }
+  }
+}
+
+// Decodes the operands of the instruction at `iptr`, for the machine and for
+// `Exec`. Parameters are numbered from 1 like in the instruction.
+struct Operands<'a, C: Cell> {
+  mem: &'a Memory<C>,
+  iptr: usize,
+  rptr: usize,
+  limits: &'a Limits,
+}
+
+// Inlined, these run several times for every instruction
+impl<'a, C: Cell> Operands<'a, C> {
+  #[inline]
+  fn ins(&self) -> i64 {
+    self.mem[self.iptr].saturating_to_i64()
+  }
+
+  #[inline]
+  fn mode_of(&self, ofst: usize, modes: [i64; 3]) -> Result<i64> {
+    match modes[ofst - 1] {
+      mode @ POSITION | mode @ IMMEDIATE | mode @ RELATIVE => Ok(mode),
+      mode => Err(IntcodeError::BadMode { iptr: self.iptr, ins: self.ins(), param: ofst, mode: mode }),
+    }
+  }
+
+  #[inline]
+  fn to_addr(&self, addr: i64) -> Result<usize> {
+    if addr < 0 {
+      return Err(IntcodeError::NegativeAddress { iptr: self.iptr, ins: self.ins(), addr: addr });
+    }
+    match self.limits.max_addr {
+      Some(max) if addr as u64 > max as u64 =>
+        Err(IntcodeError::AddressLimit { iptr: self.iptr, ins: self.ins(), addr: addr, limit: max }),
+      _ => Ok(addr as usize),
+    }
+  }
+
+  // Same as `to_addr` for a value read from memory
+  #[inline]
+  fn cell_addr(&self, val: &C) -> Result<usize> {
+    match val.to_i64() {
+      Some(addr) => self.to_addr(addr),
+      None => Err(IntcodeError::OutOfRange { iptr: self.iptr, ins: self.ins() }),
+    }
+  }
+
+  #[inline]
+  fn param(&self, ofst: usize, modes: [i64; 3]) -> Result<C> {
+    match self.mode_of(ofst, modes)? {
+      IMMEDIATE => Ok(self.mem.get(self.iptr + ofst)),
+      _ => Ok(self.mem.get(self.addr(ofst, modes)?)),
+    }
+  }
+
+  #[inline]
+  fn addr(&self, ofst: usize, modes: [i64; 3]) -> Result<usize> {
+    match self.mode_of(ofst, modes)? {
+      POSITION => self.cell_addr(&self.mem[self.iptr + ofst]),
+      RELATIVE => {
+        // Preserve sign for computation of the offset and address
+        let rptr_ofst = match self.mem[self.iptr + ofst].to_i64() {
+          Some(ofst) => ofst,
+          None => return Err(IntcodeError::OutOfRange { iptr: self.iptr, ins: self.ins() }),
+        };
+        match (self.rptr as i64).checked_add(rptr_ofst) {
+          Some(addr) => self.to_addr(addr),
+          None => Err(IntcodeError::Overflow { iptr: self.iptr, ins: self.ins() }),
+        }
+      },
+      _ => Err(IntcodeError::ImmediateWrite { iptr: self.iptr, ins: self.ins(), param: ofst }),
+    }
+  }
+}
+
+// Runs on i64 cells unless told otherwise, see `Cell`
+#[derive(Debug)]
+pub struct Interpreter<C: Cell = i64> {
+  pub mem: Memory<C>,
+  pub stdin: VecDeque<C>,
+  // Outputs in the order they were produced, oldest at the front
+  pub stdout: VecDeque<C>,
+  // Consulted once `stdin` is empty
+  pub input: Option<Box<dyn InputSource<C> + Send>>,
+  // Receives outputs instead of `stdout` when set
+  pub output: Option<Box<dyn OutputSink<C> + Send>>,
+
+  pub state: State<C>,
+  pub iptr: usize,
+  pub rptr: usize,
+
+  // Instructions left to execute before the machine stops with `OutOfFuel`,
+  // unlimited when None
+  pub fuel: Option<u64>,
+  pub limits: Limits,
+  pub arithmetic: Arithmetic,
+  // With `strict`, instructions outside the `isa` level are errors, and so
+  // are mode digits for parameters an instruction doesn't have
+  pub isa: Isa,
+  pub strict: bool,
+  // Instruction set, `registry::builtin()` unless replaced
+  pub opcodes: Arc<Registry<C>>,
+
+  // Records every step when set
+  pub trace: Option<Tracer>,
+  // Keeps the recent steps so that they can be undone with `step_back`
+  pub journal: Option<Journal<C>>,
+  // Counts executed instructions when set
+  pub profile: Option<Profile>,
+
+  // Value read or written by the last step
+  last_input: Option<C>,
+  last_output: Option<C>,
+  // Cells written by the last step: address, old value, new value
+  last_writes: Vec<(usize, C, C)>,
+  // Outputs produced so far, checked against `limits.max_outputs`
+  outputs: u64,
+}
+
+// Devices can't be duplicated, a clone starts without any
+impl<C: Cell> Clone for Interpreter<C> {
+  fn clone(&self) -> Interpreter<C> {
+    Interpreter {
+      mem: self.mem.clone(),
+      stdin: self.stdin.clone(),
+      stdout: self.stdout.clone(),
+      input: None,
+      output: None,
+      state: self.state.clone(),
+      iptr: self.iptr,
+      rptr: self.rptr,
+      fuel: self.fuel,
+      limits: self.limits,
+      arithmetic: self.arithmetic,
+      isa: self.isa,
+      strict: self.strict,
+      opcodes: self.opcodes.clone(),
+      trace: self.trace.clone(),
+      journal: self.journal.clone(),
+      profile: self.profile.clone(),
+      last_input: self.last_input.clone(),
+      last_output: self.last_output.clone(),
+      last_writes: self.last_writes.clone(),
+      outputs: self.outputs,
+    }
+  }
+}
+
+impl Interpreter {
+  pub fn new(mem: Vec<i64>) -> Interpreter {
+    Interpreter::from_cells(mem)
+  }
+}
+
+impl<C: Cell> Interpreter<C> {
+  pub fn from_cells(mem: Vec<C>) -> Interpreter<C> {
+    Interpreter {
+      mem: Memory::from(mem),
+      stdin: VecDeque::new(),
+      stdout: VecDeque::new(),
+      input: None,
+      output: None,
+      state: State::Idle,
+      iptr: 0,
+      rptr: 0,
+      fuel: None,
+      limits: Limits::default(),
+      arithmetic: Arithmetic::default(),
+      isa: Isa::default(),
+      strict: false,
+      opcodes: registry::builtin(),
+      trace: None,
+      journal: None,
+      profile: None,
+      last_input: None,
+      last_output: None,
+      last_writes: Vec::new(),
+      outputs: 0,
+    }
+  }
+
+  // A program on wider cells, e.g. `Interpreter::<BigInt>::from_program`
+  pub fn from_program(program: &[i64]) -> Interpreter<C> {
+    Interpreter::from_cells(program.iter().map(|&val| C::from_i64(val)).collect())
+  }
+
+  pub fn with_opcodes(mut self, opcodes: Registry<C>) -> Interpreter<C> {
+    self.opcodes = Arc::new(opcodes);
+    self
+  }
+
+  // Rejects everything outside `isa`, see `strict`
+  pub fn with_strict_isa(mut self, isa: Isa) -> Interpreter<C> {
+    self.isa = isa;
+    self.strict = true;
+    self
+  }
+
+  // Operands of the instruction at `iptr`
+  fn operands(&self) -> Operands<'_, C> {
+    Operands { mem: &self.mem, iptr: self.iptr, rptr: self.rptr, limits: &self.limits }
+  }
+
+  // Raw instruction at `iptr`, saturated if it doesn't fit in an i64
+  fn ins(&self) -> i64 {
+    self.operands().ins()
+  }
+
+  fn check_isa(&self, decoded: Decoded) -> Result<()> {
+    let ins = self.ins();
+    if !self.isa.has_opcode(decoded.op) {
+      return Err(IntcodeError::UnknownOpcode { iptr: self.iptr, ins: ins });
+    }
+    let params = decoded.size - 1;
+    for param in 0..params {
+      if !self.isa.has_mode(decoded.modes[param]) {
+        return Err(IntcodeError::BadMode { iptr: self.iptr, ins: ins, param: param + 1, mode: decoded.modes[param] });
+      }
+    }
+    // Digits left of the last parameter's mode
+    let mut rest = ins / 100 / TRIMMERS[params];
+    let mut param = params + 1;
+    while rest != 0 {
+      if rest % 10 != 0 {
+        return Err(IntcodeError::BadMode { iptr: self.iptr, ins: ins, param: param, mode: rest % 10 });
+      }
+      rest /= 10;
+      param += 1;
+    }
+    Ok(())
+  }
+
+  pub fn execute(&mut self) -> Result<State<C>> {
+    loop {
+      self.step()?;
+      if !self.state.can_continue() {
+        return Ok(self.state.clone());
+      }
+    }
+  }
+
+  // Runs until the next output, or until the machine needs input or halts
+  pub fn run_until_output(&mut self) -> Result<State<C>> {
+    loop {
+      match self.step()? {
+        State::Idle | State::Running => continue,
+        state => return Ok(state),
+      }
+    }
+  }
+
+  // Runs until the machine reaches an input instruction while `stdin` is
+  // empty, without asking an attached input device, or until it halts. Any
+  // instruction that reads input counts, registered ones too.
+  pub fn run_until_input(&mut self) -> Result<State<C>> {
+    let mut output = self.output.take();
+    let res = loop {
+      match self.step_with(None, output.as_mut().map(|dev| &mut **dev as &mut dyn OutputSink<C>)) {
+        Ok(state) if state.can_continue() => (),
+        res => break res,
+      }
+    };
+    self.output = output;
+    res
+  }
+
+  // Runs until `n` more outputs were produced, stops early if the machine
+  // needs input or halts
+  pub fn run_until_n_outputs(&mut self, n: usize) -> Result<State<C>> {
+    if n == 0 {
+      return Ok(self.state.clone());
+    }
+    let mut count = 0;
+    loop {
+      match self.run_until_output()? {
+        State::OutputReady(_) if count + 1 < n => count += 1,
+        state => return Ok(state),
+      }
+    }
+  }
+
+  // Like `execute`, but running out of input or fuel is an error instead of
+  // an interrupt
+  pub fn run_to_halt(&mut self) -> Result<()> {
+    match self.execute()? {
+      State::AwaitingInput => Err(IntcodeError::InputExhausted { iptr: self.iptr, ins: self.ins() }),
+      State::OutOfFuel => Err(IntcodeError::OutOfFuel { iptr: self.iptr, ins: self.ins() }),
+      _ => Ok(()),
+    }
+  }
+
+  pub fn execute_with(&mut self, mut input: Option<&mut dyn InputSource<C>>, mut output: Option<&mut dyn OutputSink<C>>) -> Result<State<C>> {
+    loop {
+      self.step_with(
+        input.as_mut().map(|dev| &mut **dev as &mut dyn InputSource<C>),
+        output.as_mut().map(|dev| &mut **dev as &mut dyn OutputSink<C>))?;
+      if !self.state.can_continue() {
+        return Ok(self.state.clone());
+      }
+    }
+  }
+
+  // On error the machine is left untouched, pointing at the faulting instruction
+  pub fn step(&mut self) -> Result<State<C>> {
+    let mut input = self.input.take();
+    let mut output = self.output.take();
+    let res = self.step_with(
+      input.as_mut().map(|dev| &mut **dev as &mut dyn InputSource<C>),
+      output.as_mut().map(|dev| &mut **dev as &mut dyn OutputSink<C>));
+    self.input = input;
+    self.output = output;
+    res
+  }
+
+  // Same as `step`, with devices borrowed from the host instead of the attached ones
+  pub fn step_with(&mut self, input: Option<&mut dyn InputSource<C>>, output: Option<&mut dyn OutputSink<C>>) -> Result<State<C>> {
+    // Refused steps aren't traced or journaled, nothing happened. A halted
+    // machine stays halted.
+    if self.fuel == Some(0) && self.state != State::Halted {
+      self.state = State::OutOfFuel;
+      return Ok(self.state.clone());
+    }
+    let before = self.trace.as_ref().map(|_| trace::Before::capture(self));
+    let undo = self.journal.as_ref().map(|journal| (journal.before(self), self.stdout.len()));
+    let (iptr, ins) = (self.iptr, self.ins());
+    let res = self.step_ins(input, output);
+    if let Some(mut profile) = self.profile.take() {
+      profile.record(iptr, ins, self, &res);
+      self.profile = Some(profile);
+    }
+    if let (Some(before), Some(mut tracer)) = (before, self.trace.take()) {
+      tracer.record(&before, self, &res);
+      self.trace = Some(tracer);
+    }
+    match (undo, self.journal.take()) {
+      (Some((undo, stdout_len)), Some(mut journal)) => {
+        // Faulted steps changed nothing, there is nothing to undo
+        if res.is_ok() {
+          journal.push(undo, stdout_len, self);
+        }
+        self.journal = Some(journal);
+      },
+      (_, journal) => self.journal = journal,
+    }
+    res
+  }
+
+  fn step_ins(&mut self, input: Option<&mut dyn InputSource<C>>, output: Option<&mut dyn OutputSink<C>>) -> Result<State<C>> {
+    let (prev, rptr, stdout_len) = (self.state.clone(), self.rptr, self.stdout.len());
+    self.state = State::Running;
+    self.last_input = None;
+    self.last_output = None;
+    self.last_writes.clear();
+    match self.exec_ins(input, output) {
+      Ok(state) => {
+        // Waiting for input doesn't execute anything, and neither does
+        // stepping a machine that already halted
+        if state != State::AwaitingInput && prev != State::Halted {
+          if let Some(fuel) = self.fuel.as_mut() {
+            *fuel -= 1;
+          }
+        }
+        self.state = state.clone();
+        Ok(state)
+      },
+      Err(e) => {
+        // Undo whatever the handler did before it failed. An output that
+        // already went to a device can't be taken back.
+        for (addr, old, _) in self.last_writes.drain(..).rev() {
+          self.mem[addr] = old;
+        }
+        if let Some(inp) = self.last_input.take() {
+          self.stdin.push_front(inp);
+        }
+        if self.last_output.take().is_some() {
+          self.outputs -= 1;
+        }
+        self.stdout.truncate(stdout_len);
+        self.rptr = rptr;
+        self.state = prev;
+        Err(e)
+      },
+    }
+  }
+
+  fn exec_ins(&mut self, input: Option<&mut dyn InputSource<C>>, output: Option<&mut dyn OutputSink<C>>) -> Result<State<C>> {
+    let decoded = self.mem.decode(self.iptr);
+    if self.strict {
+      self.check_isa(decoded)?;
+    }
+    let Decoded { op, modes, .. } = decoded;
+    let opcode = match self.opcodes.get(op) {
+      Some(opcode) => opcode,
+      None => return Err(IntcodeError::UnknownOpcode { iptr: self.iptr, ins: self.ins() }),
+    };
+    // Operands are fetched from up to the instruction's last cell
+    if let Some(max) = self.limits.max_addr {
+      let last = self.iptr + opcode.size() - 1;
+      if last > max {
+        return Err(IntcodeError::AddressLimit { iptr: self.iptr, ins: self.ins(), addr: last as i64, limit: max });
+      }
+    }
+    // Borrowed field by field so that the handler can run while the
+    // registry it came from stays borrowed
+    let mut exec = Exec {
+      modes: modes,
+      iptr: self.iptr,
+      mem: &mut self.mem,
+      rptr: &mut self.rptr,
+      stdin: &mut self.stdin,
+      stdout: &mut self.stdout,
+      limits: &self.limits,
+      arithmetic: self.arithmetic,
+      last_input: &mut self.last_input,
+      last_output: &mut self.last_output,
+      outputs: &mut self.outputs,
+      writes: &mut self.last_writes,
+      input: input.map(|dev| dev as &mut dyn InputSource<C>),
+      output: output.map(|dev| dev as &mut dyn OutputSink<C>),
+    };
+    let flow = (opcode.handler)(&mut exec)?;
+    match flow {
+      Flow::Next => self.iptr += opcode.size(),
+      Flow::Output(out) => {
+        self.iptr += opcode.size();
+        return Ok(State::OutputReady(out));
+      },
+      Flow::Jump(addr) => self.iptr = addr,
+      Flow::Wait => return Ok(State::AwaitingInput),
+      Flow::Halt => return Ok(State::Halted),
+    }
+
+    Ok(State::Idle)
+  }
+
+  // Outputs produced since the machine started, wherever they went
+  pub fn output_count(&self) -> u64 {
+    self.outputs
+  }
+
+  pub fn try_pop_output(&mut self) -> Option<C> {
+    self.stdout.pop_front()
+  }
+
+  pub fn pop_output(&mut self) -> C {
+    self.stdout.pop_front().expect("No output from program!")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn halted_machine_keeps_its_fuel() {
+    let mut vm = Interpreter::new(vec![99]);
+    vm.fuel = Some(1);
+    assert_eq!(vm.step(), Ok(State::Halted));
+    assert_eq!(vm.step(), Ok(State::Halted));
+    assert_eq!(vm.fuel, Some(0));
+  }
+
+  #[test]
+  fn fetch_past_max_addr() {
+    let mut vm = Interpreter::new(vec![104, 1, 104, 2, 99]);
+    vm.limits.max_addr = Some(1);
+    assert_eq!(vm.run_to_halt(), Err(IntcodeError::AddressLimit { iptr: 2, ins: 104, addr: 3, limit: 1 }));
+    assert_eq!(vm.stdout, vec![1]);
+  }
+}
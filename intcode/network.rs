@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::result;
+
+use super::{IntcodeError, Interpreter, State};
+
+struct Node {
+  name: String,
+  vm: Interpreter,
+  // Nodes that receive a copy of every output
+  targets: Vec<usize>,
+  last_output: Option<i64>,
+}
+
+// Machines wired together by their inputs and outputs. Every output is
+// appended to the `stdin` of each target, a node with several sources reads
+// their values in the order they were produced. Outputs of a node without
+// targets stay in its `stdout`.
+//
+// Attached `input`/`output` devices are bypassed.
+#[derive(Default)]
+pub struct Network {
+  nodes: Vec<Node>,
+  names: HashMap<String, usize>,
+}
+
+// Why `run` stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+  Halted,
+  // Names of the nodes that were still waiting for input or out of fuel
+  Deadlock(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeError {
+  pub node: String,
+  pub error: IntcodeError,
+}
+
+impl fmt::Display for NodeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "node {}: {}", self.node, self.error)
+  }
+}
+
+impl error::Error for NodeError {}
+
+impl Network {
+  pub fn new() -> Network {
+    Network::default()
+  }
+
+  fn index(&self, name: &str) -> usize {
+    match self.names.get(name) {
+      Some(&idx) => idx,
+      None => panic!("Unknown network node {}!", name),
+    }
+  }
+
+  pub fn node(&mut self, name: &str, vm: Interpreter) -> &mut Network {
+    if self.names.contains_key(name) {
+      panic!("Duplicate network node {}!", name);
+    }
+    self.names.insert(name.to_string(), self.nodes.len());
+    self.nodes.push(Node {
+      name: name.to_string(),
+      vm: vm,
+      targets: Vec::new(),
+      last_output: None,
+    });
+    self
+  }
+
+  // Queues an initial input, e.g. a phase setting
+  pub fn input(&mut self, name: &str, val: i64) -> &mut Network {
+    let idx = self.index(name);
+    self.nodes[idx].vm.stdin.push_back(val);
+    self
+  }
+
+  pub fn edge(&mut self, from: &str, to: &str) -> &mut Network {
+    let (from, to) = (self.index(from), self.index(to));
+    self.nodes[from].targets.push(to);
+    self
+  }
+
+  pub fn interpreter(&self, name: &str) -> &Interpreter {
+    &self.nodes[self.index(name)].vm
+  }
+
+  pub fn interpreter_mut(&mut self, name: &str) -> &mut Interpreter {
+    let idx = self.index(name);
+    &mut self.nodes[idx].vm
+  }
+
+  // Latest value the node produced, wherever it went
+  pub fn last_output(&self, name: &str) -> Option<i64> {
+    self.nodes[self.index(name)].last_output
+  }
+
+  // Runs the nodes in the order they were declared, each one until it halts
+  // or needs input, until every node halted or a whole round went by without
+  // any of them making progress
+  pub fn run(&mut self) -> result::Result<Outcome, NodeError> {
+    loop {
+      let mut progress = false;
+      for idx in 0..self.nodes.len() {
+        progress |= self.run_node(idx)?;
+      }
+      if !progress {
+        break;
+      }
+    }
+
+    let blocked: Vec<String> = self.nodes.iter()
+      .filter(|node| node.vm.state != State::Halted)
+      .map(|node| node.name.clone())
+      .collect();
+    if blocked.is_empty() {
+      Ok(Outcome::Halted)
+    } else {
+      Ok(Outcome::Deadlock(blocked))
+    }
+  }
+
+  // Whether the node executed anything
+  fn run_node(&mut self, idx: usize) -> result::Result<bool, NodeError> {
+    // Stepping it again would only burn fuel
+    if self.nodes[idx].vm.state == State::Halted {
+      return Ok(false);
+    }
+    let mut progress = false;
+    loop {
+      let res = self.nodes[idx].vm.step_with(None, Some(&mut |_| ()));
+      match res {
+        Ok(State::OutputReady(out)) => {
+          let node = &mut self.nodes[idx];
+          node.last_output = Some(out);
+          if node.targets.is_empty() {
+            node.vm.stdout.push_back(out);
+          }
+          for target in node.targets.clone() {
+            self.nodes[target].vm.stdin.push_back(out);
+          }
+        },
+        Ok(State::AwaitingInput) | Ok(State::OutOfFuel) | Ok(State::Halted) => return Ok(progress),
+        Ok(_) => (),
+        Err(e) => return Err(NodeError { node: self.nodes[idx].name.clone(), error: e }),
+      }
+      progress = true;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn halted_node_keeps_its_fuel() {
+    // A halts right away, B needs a few rounds of input from C
+    let mut a = Interpreter::new(vec![99]);
+    a.fuel = Some(1);
+    let mut net = Network::new();
+    net.node("A", a)
+      .node("B", Interpreter::new(vec![3, 9, 3, 9, 3, 9, 4, 9, 99, 0]))
+      .node("C", Interpreter::new(vec![104, 1, 104, 2, 104, 3, 99]))
+      .edge("C", "B");
+    assert_eq!(net.run(), Ok(Outcome::Halted));
+    assert_eq!(net.interpreter("A").state, State::Halted);
+    assert_eq!(net.last_output("B"), Some(3));
+  }
+}
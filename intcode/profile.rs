@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use super::disasm;
+use super::{digit_at, Cell, Interpreter, Registry, Result, State, IMMEDIATE, JMPF_INS, JMPT_INS, JMPT_SIZE, POSITION, RELATIVE};
+
+// Execution counts of a run. Only instructions that actually executed are
+// counted, waiting for input isn't.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+  steps: u64,
+  addrs: HashMap<usize, u64>,
+  // By raw instruction, the opcode with its mode digits
+  ins: HashMap<i64, u64>,
+  // Taken jumps to a lower address by jump address and target, the bodies
+  // of the program's loops
+  loops: HashMap<(usize, usize), u64>,
+}
+
+impl Profile {
+  pub fn new() -> Profile {
+    Profile::default()
+  }
+
+  pub fn steps(&self) -> u64 {
+    self.steps
+  }
+
+  // How many times the instruction at `addr` executed
+  pub fn count(&self, addr: usize) -> u64 {
+    self.addrs.get(&addr).cloned().unwrap_or(0)
+  }
+
+  // Raw instructions with their counts, most used first
+  pub fn ins_counts(&self) -> Vec<(i64, u64)> {
+    let mut counts: Vec<(i64, u64)> = self.ins.iter().map(|(ins, n)| (*ins, *n)).collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts
+  }
+
+  // The `n` most executed addresses, most executed first
+  pub fn hot_addrs(&self, n: usize) -> Vec<(usize, u64)> {
+    let mut counts: Vec<(usize, u64)> = self.addrs.iter().map(|(addr, n)| (*addr, *n)).collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts.truncate(n);
+    counts
+  }
+
+  // Backward jumps as (jump address, target, times taken), hottest first
+  pub fn hot_loops(&self) -> Vec<(usize, usize, u64)> {
+    let mut loops: Vec<(usize, usize, u64)> = self.loops.iter()
+      .map(|(&(from, to), n)| (from, to, *n))
+      .collect();
+    loops.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+    loops
+  }
+
+  pub(super) fn record<C: Cell>(&mut self, iptr: usize, ins: i64, vm: &Interpreter<C>, res: &Result<State<C>>) {
+    match res {
+      Ok(State::AwaitingInput) | Ok(State::OutOfFuel) | Err(_) => return,
+      Ok(_) => (),
+    }
+    self.steps += 1;
+    *self.addrs.entry(iptr).or_insert(0) += 1;
+    *self.ins.entry(ins).or_insert(0) += 1;
+    let op = ins % 100;
+    if (op == JMPT_INS || op == JMPF_INS) && vm.iptr <= iptr {
+      *self.loops.entry((iptr, vm.iptr)).or_insert(0) += 1;
+    }
+  }
+
+  // Opcode and mode usage, hottest addresses and hot loops. `vm` is only
+  // used to show the instructions.
+  pub fn table<C: Cell>(&self, vm: &Interpreter<C>) -> String {
+    let mut out = format!("{} steps\n", self.steps);
+
+    out.push_str("\ninstructions\n");
+    out.push_str(&format!("{:>12}  {:>6}  {:<5} {}\n", "count", "ins", "op", "modes"));
+    for (ins, n) in self.ins_counts() {
+      let (name, modes) = describe_ins(ins, &vm.opcodes);
+      out.push_str(&format!("{:>12}  {:>6}  {:<5} {}\n", n, ins, name, modes));
+    }
+
+    out.push_str("\nhot addresses\n");
+    out.push_str(&format!("{:>12}  {:>6}  {}\n", "count", "addr", "instruction"));
+    for (addr, n) in self.hot_addrs(20) {
+      out.push_str(&format!("{:>12}  {:>6}  {}\n", n, addr, describe_at(vm, addr)));
+    }
+
+    out.push_str("\nhot loops\n");
+    out.push_str(&format!("{:>12}  {:>6}  {:>6}  {}\n", "count", "jump", "target", "length"));
+    for (from, to, n) in self.hot_loops() {
+      out.push_str(&format!("{:>12}  {:>6}  {:>6}  {}\n", n, from, to, from + JMPT_SIZE - to));
+    }
+    out
+  }
+
+  // Lines of `disasm::disassemble_with` as `disasm::format_line` shows them,
+  // with the execution count in front of every line that ran
+  pub fn annotate<C: Cell>(&self, mem: &[i64], opcodes: &Registry<C>) -> String {
+    let mut out = String::new();
+    for line in disasm::disassemble_with(mem, opcodes) {
+      let count = match self.count(line.addr()) {
+        0 => String::new(),
+        n => n.to_string(),
+      };
+      out.push_str(&format!("{:>12} {}\n", count, disasm::format_line(mem, &line)));
+    }
+    out
+  }
+}
+
+// Mnemonic and one letter per parameter mode, e.g. `mult` and `PIP` for 1002
+fn describe_ins<C: Cell>(ins: i64, opcodes: &Registry<C>) -> (String, String) {
+  let info = match opcodes.get(ins % 100) {
+    Some(info) => info,
+    None => return ("?".to_string(), String::new()),
+  };
+  let modes = (0..info.params.len())
+    .map(|i| match digit_at(ins / 100, i) {
+      POSITION => 'P',
+      IMMEDIATE => 'I',
+      RELATIVE => 'R',
+      _ => '?',
+    })
+    .collect();
+  (info.name.clone(), modes)
+}
+
+fn describe_at<C: Cell>(vm: &Interpreter<C>, addr: usize) -> String {
+  match disasm::decode_mem(&vm.mem, &vm.opcodes, addr) {
+    Some(ins) => ins.to_string(),
+    None => format!("DATA {}", vm.mem.get(addr)),
+  }
+}
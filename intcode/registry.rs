@@ -0,0 +1,408 @@
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use super::disasm::{OpInfo, Role, OPCODES};
+use super::*;
+
+// What the machine does after an instruction's handler returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow<C = i64> {
+  // Continue with the instruction after this one
+  Next,
+  // Same as `Next`, reporting the value as `State::OutputReady`
+  Output(C),
+  Jump(usize),
+  // Stay on this instruction until there is input
+  Wait,
+  Halt,
+}
+
+pub type Handler<C> = dyn Fn(&mut Exec<C>) -> Result<Flow<C>> + Send + Sync;
+
+pub struct Opcode<C: Cell = i64> {
+  pub code: i64,
+  pub name: String,
+  pub params: Vec<Role>,
+  pub handler: Box<Handler<C>>,
+}
+
+impl<C: Cell> Opcode<C> {
+  pub fn size(&self) -> usize {
+    self.params.len() + 1
+  }
+
+  // For decoding with the machine's instruction set, see `disasm::decode_with`
+  pub fn info(&self) -> OpInfo {
+    OpInfo {
+      code: self.code,
+      name: self.name.clone().into(),
+      params: self.params.clone().into(),
+    }
+  }
+}
+
+impl<C: Cell> fmt::Debug for Opcode<C> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Opcode {{ code: {}, name: {:?}, params: {:?} }}", self.code, self.name, self.params)
+  }
+}
+
+// The instruction set of a machine, by opcode. `Registry::builtin()` holds
+// the ten standard instructions and can be extended, e.g. with division:
+//
+//   let mut registry = Registry::builtin();
+//   registry.register(10, "div", &[Role::Read, Role::Read, Role::Write], |exec| {
+//     let (a, b) = (exec.param(1)?, exec.param(2)?);
+//     if b == 0 {
+//       return Err(exec.fault("division by zero"));
+//     }
+//     let addr = exec.addr(3)?;
+//     exec.write(addr, a / b)?;
+//     Ok(Flow::Next)
+//   });
+//   vm.opcodes = Arc::new(registry);
+#[derive(Debug)]
+pub struct Registry<C: Cell = i64> {
+  // Indexed by opcode, opcodes are the two lowest decimal digits
+  opcodes: Vec<Option<Opcode<C>>>,
+}
+
+impl<C: Cell> Registry<C> {
+  pub fn empty() -> Registry<C> {
+    Registry {
+      opcodes: (0..100).map(|_| None).collect(),
+    }
+  }
+
+  pub fn builtin() -> Registry<C> {
+    let mut registry = Registry::empty();
+    for info in OPCODES.iter() {
+      let handler: Box<Handler<C>> = match info.code {
+        ADD_INS => Box::new(|exec| {
+          let addr_res = exec.addr(3)?;
+          let res = exec.arith(exec.param(1)?, exec.param(2)?, C::checked_add, C::wrapping_add)?;
+          exec.write(addr_res, res)?;
+          Ok(Flow::Next)
+        }),
+        MULT_INS => Box::new(|exec| {
+          let addr_res = exec.addr(3)?;
+          let res = exec.arith(exec.param(1)?, exec.param(2)?, C::checked_mul, C::wrapping_mul)?;
+          exec.write(addr_res, res)?;
+          Ok(Flow::Next)
+        }),
+        INP_INS => Box::new(|exec| {
+          let addr_res = exec.addr(1)?;
+          match exec.input() {
+            Some(inp) => {
+              exec.write(addr_res, inp)?;
+              Ok(Flow::Next)
+            },
+            None => Ok(Flow::Wait),
+          }
+        }),
+        OUT_INS => Box::new(|exec| {
+          let out = exec.param(1)?;
+          exec.output(out.clone())?;
+          Ok(Flow::Output(out))
+        }),
+        JMPT_INS => Box::new(|exec| {
+          if !exec.param(1)?.is_zero() {
+            Ok(Flow::Jump(exec.to_addr(exec.param(2)?)?))
+          } else {
+            Ok(Flow::Next)
+          }
+        }),
+        JMPF_INS => Box::new(|exec| {
+          if exec.param(1)?.is_zero() {
+            Ok(Flow::Jump(exec.to_addr(exec.param(2)?)?))
+          } else {
+            Ok(Flow::Next)
+          }
+        }),
+        TLS_INS => Box::new(|exec| {
+          let par1 = exec.param(1)?;
+          let par2 = exec.param(2)?;
+          let addr_res = exec.addr(3)?;
+          exec.write(addr_res, C::from_i64(if par1 < par2 { 1 } else { 0 }))?;
+          Ok(Flow::Next)
+        }),
+        TEQ_INS => Box::new(|exec| {
+          let par1 = exec.param(1)?;
+          let par2 = exec.param(2)?;
+          let addr_res = exec.addr(3)?;
+          exec.write(addr_res, C::from_i64(if par1 == par2 { 1 } else { 0 }))?;
+          Ok(Flow::Next)
+        }),
+        SRL_INS => Box::new(|exec| {
+          let i = match exec.param(1)?.to_i64() {
+            Some(i) => i,
+            None => return Err(exec.out_of_range()),
+          };
+          match (exec.rptr() as i64).checked_add(i) {
+            Some(rptr) => exec.set_rptr(rptr)?,
+            None => return Err(exec.overflow()),
+          }
+          Ok(Flow::Next)
+        }),
+        HALT_INS => Box::new(|_| Ok(Flow::Halt)),
+        code => unreachable!("No handler for opcode {}!", code),
+      };
+      registry.opcodes[info.code as usize] = Some(Opcode {
+        code: info.code,
+        name: info.name.to_string(),
+        params: info.params.to_vec(),
+        handler: handler,
+      });
+    }
+    registry
+  }
+
+  // Adds an instruction, replacing any that had the same opcode
+  pub fn register<F>(&mut self, code: i64, name: &str, params: &[Role], handler: F)
+    where F: Fn(&mut Exec<C>) -> Result<Flow<C>> + Send + Sync + 'static {
+    if !(0..100).contains(&code) {
+      panic!("Opcode {} doesn't fit in two digits!", code);
+    }
+    if params.len() > 3 {
+      panic!("Opcode {} has more than three parameters!", code);
+    }
+    self.opcodes[code as usize] = Some(Opcode {
+      code: code,
+      name: name.to_string(),
+      params: params.to_vec(),
+      handler: Box::new(handler),
+    });
+  }
+
+  pub fn get(&self, code: i64) -> Option<&Opcode<C>> {
+    if !(0..100).contains(&code) {
+      return None;
+    }
+    self.opcodes[code as usize].as_ref()
+  }
+
+  pub fn opcodes(&self) -> impl Iterator<Item = &Opcode<C>> {
+    self.opcodes.iter().filter_map(|opcode| opcode.as_ref())
+  }
+}
+
+// The builtin registry every `Interpreter` starts with, shared by all of them
+// with the same cell type
+pub fn builtin<C: Cell>() -> Arc<Registry<C>> {
+  static BUILTIN: OnceLock<Mutex<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>> = OnceLock::new();
+  let mut registries = BUILTIN.get_or_init(|| Mutex::new(HashMap::new())).lock().unwrap();
+  let registry = registries.entry(TypeId::of::<C>())
+    .or_insert_with(|| Arc::new(Registry::<C>::builtin()));
+  registry.clone().downcast::<Registry<C>>().unwrap()
+}
+
+// What a handler gets to work with: the machine's memory, relative base and
+// queues while `iptr` is still on the instruction, and the devices of the
+// current step. Parameters are numbered from 1 like in the instruction.
+//
+// Memory only changes through `write`, so that journals and traces see every
+// cell a handler writes, and a handler that fails leaves the machine as it
+// was before the step.
+pub struct Exec<'a, C: Cell = i64> {
+  pub modes: [i64; 3],
+  pub(super) iptr: usize,
+  pub(super) mem: &'a mut Memory<C>,
+  pub(super) rptr: &'a mut usize,
+  pub(super) stdin: &'a mut VecDeque<C>,
+  pub(super) stdout: &'a mut VecDeque<C>,
+  pub(super) limits: &'a Limits,
+  pub(super) arithmetic: Arithmetic,
+  pub(super) last_input: &'a mut Option<C>,
+  pub(super) last_output: &'a mut Option<C>,
+  pub(super) outputs: &'a mut u64,
+  pub(super) writes: &'a mut Vec<(usize, C, C)>,
+  pub(super) input: Option<&'a mut dyn InputSource<C>>,
+  pub(super) output: Option<&'a mut dyn OutputSink<C>>,
+}
+
+impl<'a, C: Cell> Exec<'a, C> {
+  fn operands(&self) -> Operands<'_, C> {
+    Operands { mem: self.mem, iptr: self.iptr, rptr: *self.rptr, limits: self.limits }
+  }
+
+  fn ins(&self) -> i64 {
+    self.operands().ins()
+  }
+
+  pub fn iptr(&self) -> usize {
+    self.iptr
+  }
+
+  pub fn rptr(&self) -> usize {
+    *self.rptr
+  }
+
+  pub fn read(&self, addr: usize) -> C {
+    self.mem.get(addr)
+  }
+
+  // Value of a read parameter
+  #[inline]
+  pub fn param(&self, n: usize) -> Result<C> {
+    self.operands().param(n, self.modes)
+  }
+
+  // Address a write parameter points to
+  #[inline]
+  pub fn addr(&self, n: usize) -> Result<usize> {
+    self.operands().addr(n, self.modes)
+  }
+
+  // Checks a computed address, e.g. a jump target
+  pub fn to_addr(&self, addr: C) -> Result<usize> {
+    self.operands().cell_addr(&addr)
+  }
+
+  // The only way a handler changes memory, `addr` is checked like any other
+  #[inline]
+  pub fn write(&mut self, addr: usize, val: C) -> Result<()> {
+    let addr = self.operands().to_addr(addr as i64)?;
+    let old = self.mem.replace(addr, val.clone());
+    self.writes.push((addr, old, val));
+    Ok(())
+  }
+
+  // `a + b` or `a * b` following the machine's `arithmetic`
+  pub fn arith(&self, a: C, b: C, checked: fn(&C, &C) -> Option<C>, wrapping: fn(&C, &C) -> C) -> Result<C> {
+    match self.arithmetic {
+      Arithmetic::Wrapping => Ok(wrapping(&a, &b)),
+      Arithmetic::Checked => checked(&a, &b).ok_or_else(|| self.overflow()),
+    }
+  }
+
+  pub fn set_rptr(&mut self, rptr: i64) -> Result<()> {
+    if rptr < 0 {
+      return Err(IntcodeError::NegativeRelativeBase { iptr: self.iptr, ins: self.ins(), rptr: rptr });
+    }
+    if let Some(max) = self.limits.max_rptr {
+      if rptr as u64 > max as u64 {
+        return Err(IntcodeError::RelativeBaseLimit { iptr: self.iptr, ins: self.ins(), rptr: rptr, limit: max });
+      }
+    }
+    *self.rptr = rptr as usize;
+    Ok(())
+  }
+
+  // Next input from `stdin`, then from the input device. None means the
+  // handler should return `Flow::Wait`.
+  pub fn input(&mut self) -> Option<C> {
+    let inp = self.stdin.pop_front().or_else(|| match self.input.as_mut() {
+      Some(dev) => dev.read(),
+      None => None,
+    });
+    if inp.is_some() {
+      *self.last_input = inp.clone();
+    }
+    inp
+  }
+
+  // Sends a value to the output device or `stdout`
+  pub fn output(&mut self, out: C) -> Result<()> {
+    if let Some(max) = self.limits.max_outputs {
+      if *self.outputs >= max {
+        return Err(IntcodeError::OutputLimit { iptr: self.iptr, ins: self.ins(), limit: max });
+      }
+    }
+    *self.last_output = Some(out.clone());
+    match self.output.as_mut() {
+      Some(dev) => dev.write(out),
+      None => self.stdout.push_back(out),
+    }
+    *self.outputs += 1;
+    Ok(())
+  }
+
+  // A result that doesn't fit in a cell
+  pub fn overflow(&self) -> IntcodeError {
+    IntcodeError::Overflow { iptr: self.iptr, ins: self.ins() }
+  }
+
+  // A value used as an address or relative base that doesn't fit in an i64
+  pub fn out_of_range(&self) -> IntcodeError {
+    IntcodeError::OutOfRange { iptr: self.iptr, ins: self.ins() }
+  }
+
+  // Error for a handler to return when the instruction can't execute
+  pub fn fault(&self, msg: &'static str) -> IntcodeError {
+    IntcodeError::Fault { iptr: self.iptr, ins: self.ins(), msg: msg }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // `swap a b` exchanges two cells, then fails on a zero third operand
+  fn swap_registry() -> Registry {
+    let mut registry = Registry::builtin();
+    registry.register(20, "swap", &[Role::Write, Role::Write, Role::Read], |exec| {
+      let (a, b) = (exec.addr(1)?, exec.addr(2)?);
+      let (val_a, val_b) = (exec.read(a), exec.read(b));
+      exec.write(a, val_b)?;
+      exec.write(b, val_a)?;
+      if exec.param(3)? == 0 {
+        return Err(exec.fault("zero"));
+      }
+      Ok(Flow::Next)
+    });
+    registry
+  }
+
+  #[test]
+  fn step_back_undoes_every_write() {
+    let mut vm = Interpreter::new(vec![20, 5, 6, 1, 99, 7, 8]).with_opcodes(swap_registry());
+    vm.journal = Some(Journal::new(10));
+    assert_eq!(vm.step(), Ok(State::Idle));
+    assert_eq!((vm.mem[5], vm.mem[6]), (8, 7));
+    assert_eq!(vm.journal.as_ref().unwrap().last_write(6).unwrap().writes, vec![(5, 7, 8), (6, 8, 7)]);
+    assert!(vm.step_back());
+    assert_eq!(vm.mem.to_vec(), vec![20, 5, 6, 1, 99, 7, 8]);
+  }
+
+  #[test]
+  fn failed_handler_changes_nothing() {
+    let mut vm = Interpreter::new(vec![3, 7, 20, 5, 6, 7, 99, 0]).with_opcodes(swap_registry());
+    vm.stdin.push_back(0);
+    vm.step().unwrap();
+    let before = vm.mem.to_vec();
+    assert!(vm.step().is_err());
+    assert_eq!(vm.mem.to_vec(), before);
+    assert_eq!(vm.iptr, 2);
+  }
+
+  #[test]
+  fn decoded_with_the_machine_opcodes() {
+    let vm = Interpreter::new(vec![220, 5, 6, 1, 99, 7, 8]).with_opcodes(swap_registry());
+    let ins = disasm::decode_mem(&vm.mem, &vm.opcodes, 0).unwrap();
+    assert_eq!(ins.to_string(), "swap [rb+5], [6], [1]");
+    assert_eq!(disasm::decode_mem(&vm.mem, &builtin(), 0), None);
+  }
+
+  #[test]
+  fn run_until_registered_input() {
+    // `get a` reads into a cell like `inp`
+    let mut registry = Registry::builtin();
+    registry.register(30, "get", &[Role::Write], |exec| {
+      let addr = exec.addr(1)?;
+      match exec.input() {
+        Some(val) => exec.write(addr, val)?,
+        None => return Ok(Flow::Wait),
+      }
+      Ok(Flow::Next)
+    });
+    let mut vm = Interpreter::new(vec![104, 1, 30, 7, 4, 7, 99, 0]).with_opcodes(registry);
+    vm.input = Some(Box::new(|| Some(5)));
+    assert_eq!(vm.run_until_input(), Ok(State::AwaitingInput));
+    assert_eq!((vm.iptr, vm.stdout.len()), (2, 1));
+    vm.stdin.push_back(9);
+    assert_eq!(vm.run_until_input(), Ok(State::Halted));
+    assert_eq!(vm.stdout, vec![1, 9]);
+  }
+}
@@ -0,0 +1,290 @@
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use super::{Arithmetic, Interpreter, Isa, Memory, State, PAGE_SIZE};
+
+// Line-oriented text format, every number in decimal so values round-trip
+// exactly:
+//
+//   intcode-snapshot 2
+//   state AwaitingInput
+//   iptr 6
+//   rptr 0
+//   stdin 4,5
+//   stdout 13
+//   outputs 1
+//   fuel 5000
+//   max_outputs 10
+//   arithmetic Wrapping
+//   isa Day05
+//   strict true
+//   len 29
+//   page 0 3,26,1001,...
+//
+// `fuel` and the `max_addr`, `max_outputs` and `max_rptr` limits are left out
+// when unlimited. Only allocated memory pages are written. Attached tracers,
+// journals and profiles and the opcode registry are not part of a snapshot.
+//
+// Version 1 snapshots stop after `stdout`, loading one leaves the rest at
+// their defaults.
+pub const MAGIC: &str = "intcode-snapshot";
+pub const VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+  Io(io::Error),
+  Version(u32),
+  Format { line: usize, msg: String },
+}
+
+impl fmt::Display for SnapshotError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SnapshotError::Io(e) => write!(f, "{}", e),
+      SnapshotError::Version(v) => write!(f, "unsupported snapshot version {}, expected {}", v, VERSION),
+      SnapshotError::Format { line, msg } => write!(f, "line {}: {}", line, msg),
+    }
+  }
+}
+
+impl error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+  fn from(e: io::Error) -> SnapshotError {
+    SnapshotError::Io(e)
+  }
+}
+
+pub fn is_snapshot(text: &str) -> bool {
+  text.starts_with(MAGIC)
+}
+
+fn join(vals: &[i64]) -> String {
+  vals.iter().map(|val| val.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn format_err<T>(line: usize, msg: String) -> Result<T, SnapshotError> {
+  Err(SnapshotError::Format { line: line, msg: msg })
+}
+
+fn parse<T: FromStr>(line: usize, s: &str) -> Result<T, SnapshotError> {
+  match s.trim().parse::<T>() {
+    Ok(val) => Ok(val),
+    Err(_) => format_err(line, format!("invalid number `{}`", s)),
+  }
+}
+
+fn parse_list(line: usize, s: &str) -> Result<Vec<i64>, SnapshotError> {
+  if s.trim().is_empty() {
+    return Ok(Vec::new());
+  }
+  s.split(',').map(|val| parse(line, val)).collect()
+}
+
+fn parse_arithmetic(line: usize, s: &str) -> Result<Arithmetic, SnapshotError> {
+  match s {
+    "Wrapping" => Ok(Arithmetic::Wrapping),
+    "Checked" => Ok(Arithmetic::Checked),
+    _ => format_err(line, format!("unknown arithmetic `{}`", s)),
+  }
+}
+
+fn parse_isa(line: usize, s: &str) -> Result<Isa, SnapshotError> {
+  match s {
+    "Day02" => Ok(Isa::Day02),
+    "Day05" => Ok(Isa::Day05),
+    "Day09" => Ok(Isa::Day09),
+    _ => format_err(line, format!("unknown ISA level `{}`", s)),
+  }
+}
+
+fn parse_state(line: usize, s: &str) -> Result<State, SnapshotError> {
+  match s {
+    "Idle" => Ok(State::Idle),
+    "Running" => Ok(State::Running),
+    // Written before the state was renamed
+    "AwaitingInput" | "Interrupted" => Ok(State::AwaitingInput),
+    "OutOfFuel" => Ok(State::OutOfFuel),
+    "Halted" => Ok(State::Halted),
+    _ if s.starts_with("OutputReady(") && s.ends_with(')') =>
+      Ok(State::OutputReady(parse(line, &s["OutputReady(".len()..s.len() - 1])?)),
+    _ => format_err(line, format!("unknown state `{}`", s)),
+  }
+}
+
+impl Interpreter {
+  pub fn save<W: Write>(&self, mut out: W) -> io::Result<()> {
+    writeln!(out, "{} {}", MAGIC, VERSION)?;
+    writeln!(out, "state {:?}", self.state)?;
+    writeln!(out, "iptr {}", self.iptr)?;
+    writeln!(out, "rptr {}", self.rptr)?;
+    writeln!(out, "stdin {}", join(&self.stdin.iter().cloned().collect::<Vec<_>>()))?;
+    writeln!(out, "stdout {}", join(&self.stdout.iter().cloned().collect::<Vec<_>>()))?;
+    writeln!(out, "outputs {}", self.outputs)?;
+    if let Some(fuel) = self.fuel {
+      writeln!(out, "fuel {}", fuel)?;
+    }
+    if let Some(max) = self.limits.max_addr {
+      writeln!(out, "max_addr {}", max)?;
+    }
+    if let Some(max) = self.limits.max_outputs {
+      writeln!(out, "max_outputs {}", max)?;
+    }
+    if let Some(max) = self.limits.max_rptr {
+      writeln!(out, "max_rptr {}", max)?;
+    }
+    writeln!(out, "arithmetic {:?}", self.arithmetic)?;
+    writeln!(out, "isa {:?}", self.isa)?;
+    writeln!(out, "strict {}", self.strict)?;
+    writeln!(out, "len {}", self.mem.len())?;
+    for (idx, page) in self.mem.pages() {
+      // Trailing zeros are implied
+      let used = page.iter().rposition(|val| *val != 0).map_or(1, |i| i + 1);
+      writeln!(out, "page {} {}", idx, join(&page[..used]))?;
+    }
+    out.flush()
+  }
+
+  pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+    self.save(BufWriter::new(File::create(path)?))
+  }
+
+  pub fn load<R: BufRead>(input: R) -> Result<Interpreter, SnapshotError> {
+    let mut lines = input.lines();
+    let header = lines.next().unwrap_or(Ok(String::new()))?;
+    let mut words = header.split_whitespace();
+    if words.next() != Some(MAGIC) {
+      return format_err(1, "not an intcode snapshot".to_string());
+    }
+    let version = parse::<u32>(1, words.next().unwrap_or(""))?;
+    if version != 1 && version != VERSION {
+      return Err(SnapshotError::Version(version));
+    }
+
+    let mut vm = Interpreter::new(Vec::new());
+    let mut mem = Memory::new();
+    let mut len = 0;
+    // End of the last nonzero cell in the pages, `len` can't be below it
+    let mut used = 0;
+    for (i, text) in lines.enumerate() {
+      let line = i + 2;
+      let text = text?;
+      if text.trim().is_empty() {
+        continue;
+      }
+      let (key, rest) = match text.find(' ') {
+        Some(i) => (&text[..i], &text[i + 1..]),
+        None => (&text[..], ""),
+      };
+      match key {
+        "state" => vm.state = parse_state(line, rest)?,
+        "iptr" => vm.iptr = parse(line, rest)?,
+        "rptr" => vm.rptr = parse(line, rest)?,
+        "stdin" => vm.stdin = parse_list(line, rest)?.into_iter().collect(),
+        "stdout" => vm.stdout = parse_list(line, rest)?.into_iter().collect(),
+        "outputs" => vm.outputs = parse(line, rest)?,
+        "fuel" => vm.fuel = Some(parse(line, rest)?),
+        "max_addr" => vm.limits.max_addr = Some(parse(line, rest)?),
+        "max_outputs" => vm.limits.max_outputs = Some(parse(line, rest)?),
+        "max_rptr" => vm.limits.max_rptr = Some(parse(line, rest)?),
+        "arithmetic" => vm.arithmetic = parse_arithmetic(line, rest)?,
+        "isa" => vm.isa = parse_isa(line, rest)?,
+        "strict" => vm.strict = match rest {
+          "true" => true,
+          "false" => false,
+          _ => return format_err(line, format!("expected true or false, got `{}`", rest)),
+        },
+        "len" => len = parse(line, rest)?,
+        "page" => {
+          let (idx, cells) = match rest.find(' ') {
+            Some(i) => (parse::<usize>(line, &rest[..i])?, parse_list(line, &rest[i + 1..])?),
+            None => return format_err(line, "page without contents".to_string()),
+          };
+          if cells.len() > PAGE_SIZE {
+            return format_err(line, format!("page holds {} cells, at most {} allowed", cells.len(), PAGE_SIZE));
+          }
+          let base = match idx.checked_mul(PAGE_SIZE).filter(|base| base.checked_add(PAGE_SIZE).is_some()) {
+            Some(base) => base,
+            None => return format_err(line, format!("page {} is past the last address", idx)),
+          };
+          if let Some(last) = cells.iter().rposition(|&val| val != 0) {
+            used = used.max(base + last + 1);
+          }
+          mem.load_page(idx, &cells);
+        },
+        _ => return format_err(line, format!("unknown field `{}`", key)),
+      }
+    }
+    mem.set_len(len.max(used));
+    vm.mem = mem;
+    Ok(vm)
+  }
+
+  pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Interpreter, SnapshotError> {
+    Interpreter::load(BufReader::new(File::open(path)?))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{disasm, IntcodeError};
+
+  #[test]
+  fn legacy_state_name() {
+    let text = "intcode-snapshot 1\nstate Interrupted\niptr 0\nrptr 0\nstdin \nstdout \nlen 3\npage 0 3,0,99\n";
+    let vm = Interpreter::load(text.as_bytes()).unwrap();
+    assert_eq!(vm.state, State::AwaitingInput);
+    assert_eq!(vm.mem.to_vec(), vec![3, 0, 99]);
+  }
+
+  #[test]
+  fn len_below_pages() {
+    // Raised to the end of the last nonzero cell
+    let vm = Interpreter::load("intcode-snapshot 2\nlen 0\npage 5 1,2\n".as_bytes()).unwrap();
+    assert_eq!(vm.mem.len(), 5 * PAGE_SIZE + 2);
+    assert!(disasm::listing_mem(&vm.mem).contains(&format!("{} unallocated cells", 5 * PAGE_SIZE)));
+    // Missing entirely, and a trailing page of zeros
+    let vm = Interpreter::load("intcode-snapshot 2\npage 0 99\npage 3 0,0\n".as_bytes()).unwrap();
+    assert_eq!(vm.mem.len(), 1);
+    disasm::listing_mem(&vm.mem);
+  }
+
+  #[test]
+  fn page_past_last_address() {
+    let text = format!("intcode-snapshot 2\nlen 1\npage {} 1\n", usize::MAX / PAGE_SIZE);
+    match Interpreter::load(text.as_bytes()) {
+      Err(SnapshotError::Format { line: 3, .. }) => (),
+      res => panic!("expected a format error, got {:?}", res.map(|vm| vm.mem.len())),
+    }
+  }
+
+  #[test]
+  fn settings_round_trip() {
+    let mut vm = Interpreter::new(vec![104, 1, 104, 2, 104, 3, 99]).with_strict_isa(Isa::Day05);
+    vm.fuel = Some(100);
+    vm.limits.max_addr = Some(50);
+    vm.limits.max_outputs = Some(2);
+    vm.arithmetic = Arithmetic::Checked;
+    vm.step().unwrap();
+
+    let mut saved = Vec::new();
+    vm.save(&mut saved).unwrap();
+    let mut loaded = Interpreter::load(&saved[..]).unwrap();
+    assert_eq!(loaded.fuel, vm.fuel);
+    assert_eq!(loaded.limits, vm.limits);
+    assert_eq!(loaded.arithmetic, Arithmetic::Checked);
+    assert_eq!((loaded.isa, loaded.strict), (Isa::Day05, true));
+    assert_eq!(loaded.outputs, 1);
+    // The output limit still counts the output made before saving
+    match loaded.run_to_halt() {
+      Err(IntcodeError::OutputLimit { iptr: 4, .. }) => (),
+      res => panic!("expected OutputLimit, got {:?}", res),
+    }
+    assert_eq!(loaded.stdout, vec![1, 2]);
+  }
+}
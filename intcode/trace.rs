@@ -0,0 +1,208 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use super::{Cell, Decoded, Interpreter, Result, State};
+use super::disasm::{self, Role};
+
+// One line per executed step, fields separated by ` | `:
+//
+//   step 7 iptr 4 | add [100], #1, [100] | args 0, 1 | mem[100] 0 -> 1
+//   step 8 iptr 8 | inp [rb+1] | mem[51] 0 -> 5 | in 5
+//   step 9 iptr 10 | out [51] | args 5 | out 5
+//
+// Steps that don't finish the instruction end with `wait` (no input), `halt`
+// or `error <description>`. `args` has the value of every read operand, `?`
+// for one that couldn't be read.
+#[derive(Clone)]
+pub struct Tracer {
+  sink: Arc<Mutex<dyn Write + Send>>,
+  steps: u64,
+}
+
+impl fmt::Debug for Tracer {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Tracer {{ steps: {} }}", self.steps)
+  }
+}
+
+impl Tracer {
+  pub fn new<W: Write + Send + 'static>(sink: W) -> Tracer {
+    Tracer {
+      sink: Arc::new(Mutex::new(sink)),
+      steps: 0,
+    }
+  }
+
+  pub fn to_file<P: AsRef<Path>>(path: P) -> io::Result<Tracer> {
+    Ok(Tracer::new(BufWriter::new(File::create(path)?)))
+  }
+
+  pub fn flush(&self) -> io::Result<()> {
+    self.sink.lock().unwrap().flush()
+  }
+
+  pub fn record<C: Cell>(&mut self, before: &Before, vm: &Interpreter<C>, res: &Result<State<C>>) {
+    let line = format_step(self.steps, before, vm, res);
+    self.steps += 1;
+    // Tracing is best effort, a full disk shouldn't stop the machine
+    let _ = writeln!(self.sink.lock().unwrap(), "{}", line);
+  }
+}
+
+// What the machine looked like right before a step
+pub struct Before {
+  iptr: usize,
+  text: String,
+  args: Vec<String>,
+}
+
+impl Before {
+  pub fn capture<C: Cell>(vm: &Interpreter<C>) -> Before {
+    let modes = Decoded::new(vm.ins()).modes;
+    let (text, args) = match disasm::decode_mem(&vm.mem, &vm.opcodes, vm.iptr) {
+      Some(ins) => {
+        let args = ins.op.params.iter().enumerate()
+          .filter(|(_, &role)| role == Role::Read)
+          .map(|(i, _)| match vm.operands().param(i + 1, modes) {
+            Ok(arg) => arg.to_string(),
+            Err(_) => "?".to_string(),
+          })
+          .collect();
+        (ins.to_string(), args)
+      },
+      None => (format!("DATA {}", vm.mem[vm.iptr]), Vec::new()),
+    };
+    Before {
+      iptr: vm.iptr,
+      text: text,
+      args: args,
+    }
+  }
+}
+
+fn format_step<C: Cell>(step: u64, before: &Before, vm: &Interpreter<C>, res: &Result<State<C>>) -> String {
+  let mut line = format!("step {} iptr {} | {}", step, before.iptr, before.text);
+  if !before.args.is_empty() {
+    line.push_str(&format!(" | args {}", before.args.join(", ")));
+  }
+  match res {
+    Err(e) => {
+      line.push_str(&format!(" | error {}", e));
+      return line;
+    },
+    Ok(State::AwaitingInput) => {
+      line.push_str(" | wait");
+      return line;
+    },
+    Ok(State::Halted) => {
+      line.push_str(" | halt");
+      return line;
+    },
+    Ok(_) => (),
+  }
+  for (addr, old, new) in &vm.last_writes {
+    line.push_str(&format!(" | mem[{}] {} -> {}", addr, old, new));
+  }
+  if let Some(inp) = &vm.last_input {
+    line.push_str(&format!(" | in {}", inp));
+  }
+  if let Some(out) = &vm.last_output {
+    line.push_str(&format!(" | out {}", out));
+  }
+  line
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+  // 1-based line number, one line per step
+  pub line: usize,
+  pub left: Option<String>,
+  pub right: Option<String>,
+}
+
+// First line where two traces differ, None if they are identical
+pub fn first_divergence<A: BufRead, B: BufRead>(left: A, right: B) -> io::Result<Option<Divergence>> {
+  let mut left = left.lines();
+  let mut right = right.lines();
+  let mut line = 0;
+  loop {
+    line += 1;
+    let l = left.next().transpose()?;
+    let r = right.next().transpose()?;
+    if l.is_none() && r.is_none() {
+      return Ok(None);
+    }
+    if l != r {
+      return Ok(Some(Divergence { line: line, left: l, right: r }));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Shared with the tracer, so the test can read what it wrote
+  #[derive(Clone, Default)]
+  struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+  impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+      self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  fn trace(program: Vec<i64>, inputs: &[i64]) -> String {
+    let buffer = Buffer::default();
+    let mut vm = Interpreter::new(program);
+    vm.trace = Some(Tracer::new(buffer.clone()));
+    vm.stdin.extend(inputs.iter().cloned());
+    let _ = vm.execute();
+    let bytes = buffer.0.lock().unwrap().clone();
+    String::from_utf8(bytes).unwrap()
+  }
+
+  #[test]
+  fn format() {
+    assert_eq!(trace(vec![1001, 100, 1, 100, 3, 101, 4, 101, 99], &[5]), "\
+step 0 iptr 0 | add [100], #1, [100] | args 0, 1 | mem[100] 0 -> 1
+step 1 iptr 4 | inp [101] | mem[101] 0 -> 5 | in 5
+step 2 iptr 6 | out [101] | args 5 | out 5
+step 3 iptr 8 | halt | halt
+");
+    assert_eq!(trace(vec![3, 0, 99], &[]), "step 0 iptr 0 | inp [0] | wait\n");
+  }
+
+  #[test]
+  fn unreadable_operand() {
+    // The first operand is at a negative address
+    let text = trace(vec![2201, -1, 0, 0, 99], &[]);
+    assert_eq!(text, "step 0 iptr 0 | add [rb-1], [rb+0], [0] | args ?, 2201 | error negative address -1 at 0 (instruction 2201)\n");
+  }
+
+  #[test]
+  fn divergence() {
+    let a = "step 0 iptr 0 | halt | halt\n";
+    let b = "step 0 iptr 0 | out #1 | args 1 | out 1\nstep 1 iptr 2 | halt | halt\n";
+    assert_eq!(first_divergence(a.as_bytes(), a.as_bytes()).unwrap(), None);
+    assert_eq!(first_divergence(a.as_bytes(), b.as_bytes()).unwrap(), Some(Divergence {
+      line: 1,
+      left: Some("step 0 iptr 0 | halt | halt".to_string()),
+      right: Some("step 0 iptr 0 | out #1 | args 1 | out 1".to_string()),
+    }));
+    // One trace ends early
+    let c = "step 0 iptr 0 | out #1 | args 1 | out 1\n";
+    assert_eq!(first_divergence(b.as_bytes(), c.as_bytes()).unwrap(), Some(Divergence {
+      line: 2,
+      left: Some("step 1 iptr 2 | halt | halt".to_string()),
+      right: None,
+    }));
+  }
+}
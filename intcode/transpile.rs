@@ -0,0 +1,411 @@
+use std::collections::BTreeMap;
+
+use super::cfg;
+use super::disasm::{Instruction, Operand};
+use super::*;
+
+// Instructions with a negative constant address are left to the embedded
+// interpreter, which reports them
+fn compilable(ins: &Instruction) -> bool {
+  ins.operands.iter().enumerate().all(|(i, operand)| match *operand {
+    Operand::Position(addr) => addr >= 0,
+    Operand::Immediate(target) if i == 1 && (ins.op.code == JMPT_INS || ins.op.code == JMPF_INS) => target >= 0,
+    _ => true,
+  })
+}
+
+// Rust source of a standalone program that runs `program` the way
+// `Interpreter` does: inputs are taken from `stdin`, outputs go to `stdout`,
+// `execute` stops when the machine halts or needs input. Instructions
+// `cfg::reachable` finds are compiled, anything that was modified at runtime or only
+// turns up through a computed jump runs on an embedded interpreter. Additions
+// and multiplications follow `arithmetic`.
+//
+// Without `with_main` the file only holds the `Machine`, to be used as a
+// module.
+pub fn transpile(program: &[i64], arithmetic: Arithmetic, with_main: bool) -> String {
+  let found: BTreeMap<usize, Instruction> = cfg::reachable(program).into_iter()
+    .filter(|(_, ins)| compilable(ins))
+    .collect();
+  let mut sizes = vec![0; program.len()];
+  for (addr, ins) in &found {
+    sizes[*addr] = ins.size();
+  }
+  let mut code = vec![false; program.len()];
+  for (addr, ins) in &found {
+    code[*addr..*addr + ins.size()].fill(true);
+  }
+
+  let mut arms = String::new();
+  for (addr, ins) in &found {
+    arms.push_str(&format!("        // {}\n", ins));
+    arms.push_str(&format!("        {} => {{\n", addr));
+    for line in compile(*addr, program[*addr], ins) {
+      arms.push_str(&format!("          {}\n", line));
+    }
+    arms.push_str("        },\n");
+  }
+
+  let mut out = String::new();
+  out.push_str(&format!(
+    "// Generated by `transpile` from an Intcode program of {} cells, {} of\n\
+     // its instructions are compiled\n", program.len(), found.len()));
+  out.push_str(HEADER);
+  out.push_str("// Overflowing additions and multiplications are faults, they wrap otherwise\n");
+  out.push_str(&format!("const CHECKED: bool = {};\n", arithmetic == Arithmetic::Checked));
+  out.push_str(&format!("pub const PROGRAM: [i64; {}] = [{}];\n", program.len(), join(program)));
+  out.push_str("// Cells that belong to a compiled instruction\n");
+  out.push_str(&format!("const CODE: [bool; {}] = [{}];\n", code.len(), join(&code)));
+  out.push_str("// Size of the compiled instruction starting at every address, 0 for none\n");
+  out.push_str(&format!("const SIZES: [u8; {}] = [{}];\n", sizes.len(), join(&sizes)));
+  out.push_str(&RUNTIME.replace("        // ARMS\n", &arms));
+  if with_main {
+    out.push_str(MAIN);
+  }
+  out
+}
+
+fn join<T: ToString>(vals: &[T]) -> String {
+  vals.iter().map(|val| val.to_string()).collect::<Vec<_>>().join(",")
+}
+
+// Statements for one instruction, in the order the interpreter evaluates
+// its operands so faults come out the same
+fn compile(addr: usize, raw: i64, ins: &Instruction) -> Vec<String> {
+  let next = addr + ins.size();
+  let read = |i: usize| match ins.operands[i] {
+    Operand::Position(at) => format!("self.read({})", at),
+    Operand::Immediate(val) => format!("({})", val),
+    Operand::Relative(ofst) => format!("self.read(self.rel({}, {}, {})?)", ofst, addr, raw),
+  };
+  let write = |i: usize| match ins.operands[i] {
+    Operand::Position(at) => format!("{}", at),
+    Operand::Relative(ofst) => format!("self.rel({}, {}, {})?", ofst, addr, raw),
+    Operand::Immediate(_) => unreachable!(),
+  };
+  let jump = || match ins.operands[1] {
+    Operand::Immediate(target) => format!("{}", target),
+    _ => format!("self.addr({}, {}, {})?", read(1), addr, raw),
+  };
+
+  match ins.op.code {
+    ADD_INS | MULT_INS => vec![
+      format!("let dst = {};", write(2)),
+      format!("let val = self.{}({}, {}, {}, {})?;", if ins.op.code == ADD_INS { "add" } else { "mul" }, read(0), read(1), addr, raw),
+      "self.write(dst, val);".to_string(),
+      format!("self.iptr = {};", next),
+    ],
+    INP_INS => vec![
+      format!("let dst = {};", write(0)),
+      "match self.stdin.pop_front() {".to_string(),
+      "  Some(val) => self.write(dst, val),".to_string(),
+      "  None => {".to_string(),
+      "    self.state = State::AwaitingInput;".to_string(),
+      "    return Ok(self.state);".to_string(),
+      "  },".to_string(),
+      "}".to_string(),
+      format!("self.iptr = {};", next),
+    ],
+    OUT_INS => vec![
+      format!("let val = {};", read(0)),
+      "self.stdout.push_back(val);".to_string(),
+      format!("self.iptr = {};", next),
+    ],
+    JMPT_INS | JMPF_INS => vec![
+      format!("self.iptr = if {} {} 0 {{", read(0), if ins.op.code == JMPT_INS { "!=" } else { "==" }),
+      format!("  {}", jump()),
+      "} else {".to_string(),
+      format!("  {}", next),
+      "};".to_string(),
+    ],
+    TLS_INS | TEQ_INS => vec![
+      format!("let par1 = {};", read(0)),
+      format!("let par2 = {};", read(1)),
+      format!("let dst = {};", write(2)),
+      format!("self.write(dst, if par1 {} par2 {{ 1 }} else {{ 0 }});", if ins.op.code == TLS_INS { "<" } else { "==" }),
+      format!("self.iptr = {};", next),
+    ],
+    SRL_INS => vec![
+      format!("let ofst = {};", read(0)),
+      format!("self.move_rptr(ofst, {}, {})?;", addr, raw),
+      format!("self.iptr = {};", next),
+    ],
+    _ => vec![
+      "self.state = State::Halted;".to_string(),
+      "return Ok(self.state);".to_string(),
+    ],
+  }
+}
+
+const HEADER: &str = r#"#![allow(dead_code, unused_imports, unused_parens)]
+
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fmt;
+use std::process;
+
+"#;
+
+const RUNTIME: &str = r#"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+  Idle,
+  // Stopped at an input instruction with nothing to read
+  AwaitingInput,
+  Halted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fault {
+  // Name of the interpreter's matching `IntcodeError` variant
+  pub kind: &'static str,
+  pub msg: String,
+  pub iptr: usize,
+  pub ins: i64,
+}
+
+impl fmt::Display for Fault {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} at {} (instruction {})", self.msg, self.iptr, self.ins)
+  }
+}
+
+fn fault(kind: &'static str, msg: String, iptr: usize, ins: i64) -> Fault {
+  Fault { kind, msg, iptr, ins }
+}
+
+const PAGE_BITS: usize = 10;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+const PAGE_MASK: usize = PAGE_SIZE - 1;
+
+#[derive(Debug, Clone)]
+pub struct Machine {
+  // Paged, pages are only allocated once something is written to them
+  pages: HashMap<usize, Box<[i64]>>,
+  pub stdin: VecDeque<i64>,
+  pub stdout: VecDeque<i64>,
+  pub state: State,
+  pub iptr: usize,
+  pub rptr: usize,
+  // Compiled cells that no longer hold what was compiled
+  modified: Vec<bool>,
+  code_dirty: bool,
+}
+
+impl Machine {
+  pub fn new() -> Machine {
+    let mut machine = Machine {
+      pages: HashMap::new(),
+      stdin: VecDeque::new(),
+      stdout: VecDeque::new(),
+      state: State::Idle,
+      iptr: 0,
+      rptr: 0,
+      modified: vec![false; PROGRAM.len()],
+      code_dirty: false,
+    };
+    for (addr, &val) in PROGRAM.iter().enumerate() {
+      machine.write(addr, val);
+    }
+    machine
+  }
+
+  pub fn read(&self, addr: usize) -> i64 {
+    match self.pages.get(&(addr >> PAGE_BITS)) {
+      Some(page) => page[addr & PAGE_MASK],
+      None => 0,
+    }
+  }
+
+  // Writes from the host count too, changed code runs on the embedded
+  // interpreter
+  pub fn write(&mut self, addr: usize, val: i64) {
+    let page = self.pages.entry(addr >> PAGE_BITS)
+      .or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice());
+    page[addr & PAGE_MASK] = val;
+    if addr < PROGRAM.len() && CODE[addr] && val != PROGRAM[addr] {
+      self.modified[addr] = true;
+      self.code_dirty = true;
+    }
+  }
+
+  fn add(&self, a: i64, b: i64, iptr: usize, ins: i64) -> Result<i64, Fault> {
+    if CHECKED {
+      a.checked_add(b).ok_or_else(|| fault("Overflow", format!("{} + {} overflows", a, b), iptr, ins))
+    } else {
+      Ok(a.wrapping_add(b))
+    }
+  }
+
+  fn mul(&self, a: i64, b: i64, iptr: usize, ins: i64) -> Result<i64, Fault> {
+    if CHECKED {
+      a.checked_mul(b).ok_or_else(|| fault("Overflow", format!("{} * {} overflows", a, b), iptr, ins))
+    } else {
+      Ok(a.wrapping_mul(b))
+    }
+  }
+
+  fn addr(&self, addr: i64, iptr: usize, ins: i64) -> Result<usize, Fault> {
+    if addr < 0 {
+      return Err(fault("NegativeAddress", format!("negative address {}", addr), iptr, ins));
+    }
+    Ok(addr as usize)
+  }
+
+  fn rel(&self, ofst: i64, iptr: usize, ins: i64) -> Result<usize, Fault> {
+    match (self.rptr as i64).checked_add(ofst) {
+      Some(addr) => self.addr(addr, iptr, ins),
+      None => Err(fault("Overflow", format!("relative address {} + {} overflows", self.rptr, ofst), iptr, ins)),
+    }
+  }
+
+  fn move_rptr(&mut self, ofst: i64, iptr: usize, ins: i64) -> Result<(), Fault> {
+    let rptr = match (self.rptr as i64).checked_add(ofst) {
+      Some(rptr) => rptr,
+      None => return Err(fault("Overflow", format!("relative base {} + {} overflows", self.rptr, ofst), iptr, ins)),
+    };
+    if rptr < 0 {
+      return Err(fault("NegativeRelativeBase", format!("relative base would become {}", rptr), iptr, ins));
+    }
+    self.rptr = rptr as usize;
+    Ok(())
+  }
+
+  // Whether the compiled instruction at `iptr` was written over
+  fn stale(&self) -> bool {
+    let size = SIZES.get(self.iptr).cloned().unwrap_or(0) as usize;
+    (self.iptr..self.iptr + size).any(|addr| self.modified[addr])
+  }
+
+  // Runs until the machine halts or needs input
+  pub fn execute(&mut self) -> Result<State, Fault> {
+    loop {
+      if self.code_dirty && self.stale() {
+        if let Some(state) = self.step_interp()? {
+          return Ok(state);
+        }
+        continue;
+      }
+      match self.iptr {
+        // ARMS
+        _ => if let Some(state) = self.step_interp()? {
+          return Ok(state);
+        },
+      }
+    }
+  }
+
+  // Embedded interpreter, used for code that wasn't compiled
+
+  fn mode(&self, ofst: usize) -> Result<i64, Fault> {
+    let ins = self.read(self.iptr);
+    let mode = (ins / [100, 1000, 10000][ofst - 1]) % 10;
+    match mode {
+      0 | 1 | 2 => Ok(mode),
+      _ => Err(fault("BadMode", format!("invalid mode {} for parameter {}", mode, ofst), self.iptr, ins)),
+    }
+  }
+
+  fn param_addr(&self, ofst: usize) -> Result<usize, Fault> {
+    let ins = self.read(self.iptr);
+    let val = self.read(self.iptr + ofst);
+    match self.mode(ofst)? {
+      0 => self.addr(val, self.iptr, ins),
+      2 => self.rel(val, self.iptr, ins),
+      _ => Err(fault("ImmediateWrite", format!("write through immediate mode parameter {}", ofst), self.iptr, ins)),
+    }
+  }
+
+  fn param(&self, ofst: usize) -> Result<i64, Fault> {
+    match self.mode(ofst)? {
+      1 => Ok(self.read(self.iptr + ofst)),
+      _ => Ok(self.read(self.param_addr(ofst)?)),
+    }
+  }
+
+  // Executes one instruction, Some once the machine stops
+  fn step_interp(&mut self) -> Result<Option<State>, Fault> {
+    let ins = self.read(self.iptr);
+    match ins % 100 {
+      1 | 2 => {
+        let dst = self.param_addr(3)?;
+        let (par1, par2) = (self.param(1)?, self.param(2)?);
+        let val = if ins % 100 == 1 {
+          self.add(par1, par2, self.iptr, ins)?
+        } else {
+          self.mul(par1, par2, self.iptr, ins)?
+        };
+        self.write(dst, val);
+        self.iptr += 4;
+      },
+      3 => {
+        let dst = self.param_addr(1)?;
+        match self.stdin.pop_front() {
+          Some(val) => self.write(dst, val),
+          None => {
+            self.state = State::AwaitingInput;
+            return Ok(Some(self.state));
+          },
+        }
+        self.iptr += 2;
+      },
+      4 => {
+        let val = self.param(1)?;
+        self.stdout.push_back(val);
+        self.iptr += 2;
+      },
+      5 | 6 => {
+        if (self.param(1)? != 0) == (ins % 100 == 5) {
+          self.iptr = self.addr(self.param(2)?, self.iptr, ins)?;
+        } else {
+          self.iptr += 3;
+        }
+      },
+      7 | 8 => {
+        let par1 = self.param(1)?;
+        let par2 = self.param(2)?;
+        let dst = self.param_addr(3)?;
+        let cond = if ins % 100 == 7 { par1 < par2 } else { par1 == par2 };
+        self.write(dst, if cond { 1 } else { 0 });
+        self.iptr += 4;
+      },
+      9 => {
+        let ofst = self.param(1)?;
+        self.move_rptr(ofst, self.iptr, ins)?;
+        self.iptr += 2;
+      },
+      99 => {
+        self.state = State::Halted;
+        return Ok(Some(self.state));
+      },
+      op => return Err(fault("UnknownOpcode", format!("unknown opcode {}", op), self.iptr, ins)),
+    }
+    Ok(None)
+  }
+}
+"#;
+
+const MAIN: &str = r#"
+// Inputs are taken from the command line, outputs are printed one per line
+fn main() {
+  let mut machine = Machine::new();
+  for arg in env::args().skip(1) {
+    machine.stdin.push_back(arg.parse().expect("Invalid input value!"));
+  }
+  let res = machine.execute();
+  for out in &machine.stdout {
+    println!("{}", out);
+  }
+  match res {
+    Ok(State::Halted) => (),
+    Ok(state) => {
+      eprintln!("stopped: {:?}", state);
+      process::exit(1);
+    },
+    Err(e) => {
+      eprintln!("error: {}", e);
+      process::exit(1);
+    },
+  }
+}
+"#;
@@ -0,0 +1,34 @@
+pub mod intcode;
+
+use std::env;
+use intcode::{load_program, Interpreter, Profile};
+
+fn read_program(path: &str) -> Vec<i64> {
+  load_program(path).unwrap_or_else(|e| panic!("{}", e))
+}
+
+// Runs a program with profiling on and prints the counts, followed by the
+// annotated disassembly: profile <program> [input...]
+fn main() {
+  let args: Vec<String> = env::args().collect();
+  if args.len() < 2 {
+    panic!("Usage: profile <inputs/dayNN.txt> [input...]");
+  }
+
+  let program = read_program(&args[1]);
+  let mut interpreter = Interpreter::new(program.clone());
+  interpreter.profile = Some(Profile::new());
+  for inp in &args[2..] {
+    interpreter.stdin.push_back(inp.parse().expect("Invalid input value!"));
+  }
+  match interpreter.execute() {
+    Ok(state) => println!("{:?}, outputs: {:?}", state, interpreter.stdout),
+    Err(e) => println!("error: {}", e),
+  }
+
+  let profile = interpreter.profile.as_ref().unwrap();
+  println!();
+  print!("{}", profile.table(&interpreter));
+  println!();
+  print!("{}", profile.annotate(&program, &interpreter.opcodes));
+}
@@ -0,0 +1,24 @@
+pub mod intcode;
+
+use std::env;
+use intcode::{load_program, Interpreter, State};
+
+fn read_program(path: &str) -> Vec<i64> {
+  load_program(path).unwrap_or_else(|e| panic!("{}", e))
+}
+
+// Runs a text-driven program on the terminal, lines typed in become its
+// input: terminal <program>
+fn main() {
+  let args: Vec<String> = env::args().collect();
+  if args.len() < 2 {
+    panic!("Usage: terminal <program>");
+  }
+
+  let mut interpreter = Interpreter::new(read_program(&args[1]));
+  match interpreter.run_ascii_terminal() {
+    Ok(State::Halted) => (),
+    Ok(state) => eprintln!("stopped: {:?}", state),
+    Err(e) => eprintln!("error: {}", e),
+  }
+}
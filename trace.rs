@@ -0,0 +1,28 @@
+pub mod intcode;
+
+use std::env;
+use intcode::{load_program, Interpreter, Tracer};
+
+fn read_program(path: &str) -> Vec<i64> {
+  load_program(path).unwrap_or_else(|e| panic!("{}", e))
+}
+
+// Runs a program with tracing on: trace <program> <out.trace> [input...]
+fn main() {
+  let args: Vec<String> = env::args().collect();
+  if args.len() < 3 {
+    panic!("Usage: trace <inputs/dayNN.txt> <out.trace> [input...]");
+  }
+
+  let mut interpreter = Interpreter::new(read_program(&args[1]));
+  interpreter.trace = Some(Tracer::to_file(&args[2]).expect("Cannot create trace file!"));
+  for inp in &args[3..] {
+    interpreter.stdin.push_back(inp.parse().expect("Invalid input value!"));
+  }
+  let res = interpreter.execute();
+  interpreter.trace.as_ref().unwrap().flush().expect("Cannot write trace file!");
+  match res {
+    Ok(state) => println!("{:?}, outputs: {:?}", state, interpreter.stdout),
+    Err(e) => println!("error: {}", e),
+  }
+}
@@ -0,0 +1,28 @@
+pub mod intcode;
+
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::process;
+use intcode::trace;
+
+fn open(path: &str) -> BufReader<File> {
+  BufReader::new(File::open(path).expect("Cannot open trace!"))
+}
+
+fn main() {
+  let args: Vec<String> = env::args().collect();
+  if args.len() != 3 {
+    panic!("Usage: tracediff <a.trace> <b.trace>");
+  }
+
+  match trace::first_divergence(open(&args[1]), open(&args[2])).expect("Cannot read trace!") {
+    None => println!("traces are identical"),
+    Some(div) => {
+      println!("traces diverge at line {}", div.line);
+      println!("< {}", div.left.unwrap_or("<end of trace>".to_string()));
+      println!("> {}", div.right.unwrap_or("<end of trace>".to_string()));
+      process::exit(1);
+    },
+  }
+}
@@ -0,0 +1,38 @@
+pub mod intcode;
+
+use std::env;
+use std::fs;
+use intcode::{load_program, transpile, Arithmetic};
+
+fn read_program(path: &str) -> Vec<i64> {
+  load_program(path).unwrap_or_else(|e| panic!("{}", e))
+}
+
+// Removes `flag` from the arguments, whether it was there
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+  match args.iter().position(|arg| arg == flag) {
+    Some(idx) => {
+      args.remove(idx);
+      true
+    },
+    None => false,
+  }
+}
+
+// Translates a program to Rust: transpile [--lib] [--checked] <program> [out.rs]
+// The source goes to stdout without an output file, `--lib` leaves out `main`,
+// `--checked` makes overflows faults instead of wrapping.
+fn main() {
+  let mut args: Vec<String> = env::args().skip(1).collect();
+  let with_main = !take_flag(&mut args, "--lib");
+  let arithmetic = if take_flag(&mut args, "--checked") { Arithmetic::Checked } else { Arithmetic::Wrapping };
+  if args.is_empty() {
+    panic!("Usage: transpile [--lib] [--checked] <inputs/dayNN.txt> [out.rs]");
+  }
+
+  let source = transpile::transpile(&read_program(&args[0]), arithmetic, with_main);
+  match args.get(1) {
+    Some(path) => fs::write(path, source).expect("Cannot write output file!"),
+    None => print!("{}", source),
+  }
+}